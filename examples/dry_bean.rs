@@ -0,0 +1,63 @@
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use rust_nn::{
+    loss::SoftmaxCrossEntropy,
+    metrics::{self, Average},
+    preludes::{num_to_onehot, vec_to_array},
+    Activation, ActivationType, Adam, BatchNorm1d, Dense, Initializer, Layer, Logger, Network, Pipeline, Series, Split,
+    StandardScaler, Trainer, Transformer,
+};
+
+// Classifies the seven bean varieties of the Dry Bean dataset
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut series = Series::from_csv("data/Dry_Bean_Dataset.csv", None)?;
+    series.drop_col("Bean ID")?;
+
+    series.to_categorical("Class")?;
+    let n_classes = series.unique_in_col("Class")?.len();
+
+    // Some varieties are rare, so the held out rows keep the class proportions
+    let split = Split::stratified(&series.col("Class")?.labels()?, 0.0, 0.2, &mut rng)?;
+    let (mut train, _, mut test) = split.series(&series);
+    let labels = train.drop_col("Class")?.labels()?;
+    let test_labels = test.drop_col("Class")?.labels()?;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add(StandardScaler::new(&train.headers));
+    let inputs = pipeline.fit_transform(&train)?.to_array()?;
+    let answers = vec_to_array(labels.iter().map(|l| num_to_onehot(*l as u32, n_classes as u32)).collect());
+
+    // Dense -> BatchNorm -> ReLU blocks, the last layer gives raw class scores
+    let mut nn = Network::empty();
+    nn.seed(rng.next_u64());
+    let widths = [inputs.ncols(), 64, 32];
+    for (i, w) in widths.windows(2).enumerate() {
+        let mut dense = Dense::new(&format!("hidden{i}"), w[0], w[1]);
+        dense.init(&Initializer::HeNormal, &mut rng);
+        nn.add_layer(dense);
+        nn.add_layer(BatchNorm1d::new(&format!("hidden{i}_norm"), w[1]));
+        nn.add_layer(Activation::new(&format!("hidden{i}_activation"), ActivationType::ReLU));
+    }
+    let mut out = Dense::new("out", 32, n_classes);
+    out.init(&Initializer::GlorotUniform, &mut rng);
+    nn.add_layer(out);
+
+    let mut trainer = Trainer::new(
+        nn,
+        Box::new(SoftmaxCrossEntropy::new()),
+        Box::new(Adam::new(0.005)),
+        &mut rng,
+    );
+    trainer.epochs = 10;
+    trainer.batch_size = 64;
+    trainer.validation_split = 0.2;
+    trainer.add_callback(Logger::new(1));
+    trainer.fit(&inputs, &answers)?;
+
+    let predictions = trainer.network.output(&pipeline.transform(&test)?.to_array()?)?;
+    println!("test accuracy: {:.3}", metrics::accuracy(&predictions, &test_labels)?);
+    println!("test macro F1: {:.3}", metrics::f1(&predictions, &test_labels, Average::Macro)?);
+    println!("confusion matrix:\n{}", metrics::confusion_matrix(&predictions, &test_labels)?);
+
+    Ok(())
+}
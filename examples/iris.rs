@@ -0,0 +1,58 @@
+use rand::{rngs::StdRng, SeedableRng};
+use rust_nn::{
+    loss::SoftmaxCrossEntropy,
+    metrics::{self, Average},
+    preludes::{num_to_onehot, vec_to_array},
+    ActivationType, Adam, CosineAnnealing, EarlyStopping, Format, LinearWarmup, Logger, MinMaxScaler, Network, Pipeline,
+    Series, Split, Trainer, Transformer,
+};
+
+// Trains a small classifier on the Iris dataset with backpropagation
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let series = Series::from_csv("data/Iris.csv", None)?;
+
+    // A fifth of every species is held out for the final accuracy
+    let split = Split::stratified(&series.col("species")?.labels()?, 0.0, 0.2, &mut rng)?;
+    let (mut train, _, mut test) = split.series(&series);
+    let train_labels = train.drop_col("species")?.labels()?;
+    let test_labels = test.drop_col("species")?.labels()?;
+
+    // Fitted on the training rows only and saved with the network, so inference scales its inputs the same way
+    let mut pipeline = Pipeline::new();
+    pipeline.add(MinMaxScaler::new(&train.headers));
+    let inputs = pipeline.fit_transform(&train)?.to_array()?;
+    let answers = vec_to_array(train_labels.iter().map(|l| num_to_onehot(*l as u32, 3)).collect());
+
+    // The output layer gives raw class scores, the loss applies the softmax
+    let nn = Network::new(vec![
+        ("hidden", 4, 16, ActivationType::Tanh),
+        ("out", 16, 3, ActivationType::Linear),
+    ], &mut rng);
+
+    let mut trainer = Trainer::new(
+        nn,
+        Box::new(SoftmaxCrossEntropy::new()),
+        Box::new(Adam::new(0.01)),
+        &mut rng,
+    );
+    trainer.epochs = 100;
+    trainer.batch_size = 16;
+    trainer.validation_split = 0.2;
+    trainer.scheduler = Some(Box::new(LinearWarmup::new(5, Box::new(CosineAnnealing::new(0.01, 0.0005, 95, 1)))));
+    trainer.add_callback(EarlyStopping::new(15));
+    trainer.add_callback(Logger::new(10));
+    trainer.fit(&inputs, &answers)?;
+
+    let nn = trainer.network;
+    let predictions = nn.output(&pipeline.transform(&test)?.to_array()?)?;
+    println!("test accuracy: {:.3}", metrics::accuracy(&predictions, &test_labels)?);
+    println!("test macro F1: {:.3}", metrics::f1(&predictions, &test_labels, Average::Macro)?);
+
+    let bytes = nn.to_bytes(Format::Binary, None, Some(&pipeline))?;
+    let mut restored = Pipeline::new();
+    Network::from_bytes(&bytes, Format::Binary, None, Some(&mut restored))?;
+    println!("restored pipeline: {:?}", restored.steps);
+
+    Ok(())
+}
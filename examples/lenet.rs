@@ -0,0 +1,64 @@
+use ndarray::Array;
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rust_nn::{
+    loss::SoftmaxCrossEntropy,
+    metrics,
+    Activation, ActivationType, Adam, Conv2d, Dense, Flatten, Initializer, Layer, Logger, MaxPool2d, Network, Trainer,
+};
+
+const SIZE: usize = 8;
+
+// Noisy single channel 8x8 images, (n, 1, 8, 8), holding either a horizontal (class 0) or a vertical (class 1) bar
+fn bars(n: usize, rng: &mut StdRng) -> (Array<f64, ndarray::Ix4>, Vec<usize>) {
+    let labels: Vec<usize> = (0..n).map(|_| rng.gen_range(0..2)).collect();
+    let mut images = Array::from_shape_fn((n, 1, SIZE, SIZE), |_| rng.gen_range(0.0..0.3));
+    for (mut image, label) in images.outer_iter_mut().zip(labels.iter()) {
+        let line = rng.gen_range(0..SIZE);
+        for k in 0..SIZE {
+            let (y, x) = if *label == 0 { (line, k) } else { (k, line) };
+            image[[0, y, x]] = 1.0;
+        }
+    }
+    (images, labels)
+}
+
+// conv -> relu -> max pool -> conv -> relu -> max pool -> flatten -> dense
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let (inputs, labels) = bars(512, &mut rng);
+    let answers = Array::from_shape_fn((labels.len(), 2), |(i, c)| if labels[i] == c { 1.0 } else { 0.0 });
+
+    let mut nn = Network::empty();
+    nn.seed(rng.next_u64());
+
+    let mut conv1 = Conv2d::new("conv1", 1, 4, 3, 1, 1, (SIZE, SIZE))?;
+    conv1.init(&Initializer::HeNormal, &mut rng);
+    nn.add_layer(conv1);
+    nn.add_layer(Activation::new("conv1_activation", ActivationType::ReLU));
+    nn.add_layer(MaxPool2d::new("pool1", 4, (SIZE, SIZE), 2, 2)?);
+
+    let mut conv2 = Conv2d::new("conv2", 4, 8, 3, 1, 1, (SIZE / 2, SIZE / 2))?;
+    conv2.init(&Initializer::HeNormal, &mut rng);
+    nn.add_layer(conv2);
+    nn.add_layer(Activation::new("conv2_activation", ActivationType::ReLU));
+    nn.add_layer(MaxPool2d::new("pool2", 8, (SIZE / 2, SIZE / 2), 2, 2)?);
+
+    let flatten = Flatten::new("flatten", &[8, SIZE / 4, SIZE / 4]);
+    let mut out = Dense::new("out", flatten.n_output(), 2);
+    out.init(&Initializer::GlorotUniform, &mut rng);
+    nn.add_layer(flatten);
+    nn.add_layer(out);
+    println!("output shape: {:?}", nn.output_shape(&[1, SIZE, SIZE])?);
+
+    let mut trainer = Trainer::new(nn, Box::new(SoftmaxCrossEntropy::new()), Box::new(Adam::new(0.01)), &mut rng);
+    trainer.epochs = 15;
+    trainer.batch_size = 32;
+    trainer.add_callback(Logger::new(5));
+    trainer.fit(&inputs, &answers)?;
+
+    let (test_inputs, test_labels) = bars(256, &mut rng);
+    let predictions = trainer.network.output(&test_inputs)?;
+    println!("test accuracy: {:.3}", metrics::accuracy(&predictions, &test_labels)?);
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use rand::{rngs::StdRng, SeedableRng};
+use rust_nn::{
+    loss::Mse,
+    onnx,
+    preludes::{num_to_onehot, vec_to_array},
+    ActivationType, Adam, Network, Series, Trainer,
+};
+
+// Trains an Iris classifier, exports it to ONNX and checks the re-imported model gives the same outputs
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut series = Series::from_csv("data/Iris.csv", Some(&mut rng))?;
+    let species = series.drop_col("species")?;
+    for col in series.headers.clone() {
+        series.scale_by_max(&col)?;
+    }
+
+    let inputs = series.to_array()?;
+    let answers = vec_to_array(species.labels()?.into_iter().map(|l| num_to_onehot(l as u32, 3)).collect());
+
+    let nn = Network::new(vec![
+        ("hidden", 4, 16, ActivationType::ReLU),
+        ("out", 16, 3, ActivationType::Sigmoid),
+    ], &mut rng);
+    let mut trainer = Trainer::new(nn, Box::new(Mse::new()), Box::new(Adam::new(0.01)), &mut rng);
+    trainer.epochs = 50;
+    trainer.fit(&inputs, &answers)?;
+    let nn = trainer.network;
+
+    let path = std::env::temp_dir().join("iris.onnx");
+    let path = path.to_str().ok_or("temp dir is not valid UTF-8")?;
+    onnx::save(&nn, path)?;
+    let imported = onnx::load(path)?;
+
+    // Weights are stored as 32-bit floats in the ONNX file
+    let diff = (nn.output(&inputs)? - imported.output(&inputs)?).mapv(f64::abs).fold(0.0, |a: f64, b| a.max(*b));
+    println!("saved {path} with layers {:?}", imported.layer_names());
+    println!("max abs difference: {diff:e}");
+    assert!(diff < 1e-5);
+
+    Ok(())
+}
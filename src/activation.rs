@@ -1,24 +1,43 @@
-use ndarray::{Array, Dim};
+use std::f64::consts::{FRAC_2_SQRT_PI, FRAC_1_SQRT_2};
+use ndarray::{Array, Axis, Dim, Zip};
 use serde::{ Deserialize, Serialize };
-use rayon::prelude::*;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
 pub enum ActivationType {
   Step,
   Sigmoid,
   Tanh,
   ReLU,
+  // Slope for negative inputs
+  LeakyReLU(f64),
+  // Value approached for large negative inputs is -alpha
+  ELU(f64),
+  GELU,
+  // x * sigmoid(x), also known as Swish
+  SiLU,
+  Softplus,
+  Mish,
+  HardSigmoid,
+  // Identity, for regression heads and raw scores
+  Linear,
   Softmax
 }
 
+// sqrt(2 / pi), used by the tanh approximation of GELU
+const GELU_C: f64 = FRAC_2_SQRT_PI * FRAC_1_SQRT_2;
+const GELU_A: f64 = 0.044715;
+
 impl ActivationType {
   pub fn sigmoid(x: f64) -> f64 {
-    1.0 / (1.0 + f64::powf(2.71828, x) )
+    if x >= 0.0 {
+      1.0 / (1.0 + (-x).exp())
+    } else {
+      let e = x.exp();
+      e / (1.0 + e)
+    }
   }
   pub fn tanh(x: f64) -> f64 {
-    return 
-      (f64::powf(2.71828, x) - f64::powf(2.71828, -x)) / 
-      (f64::powf(2.71828, x) + f64::powf(2.71828, -x))
+    x.tanh()
   }
   pub fn step(x: f64) -> f64 {
     if x >= 0.5 { 1.0 } else { 0.0 }
@@ -26,21 +45,121 @@ impl ActivationType {
 
   pub fn relu(x: f64) -> f64 {
     if x <= 0.0 {
-      return 0.0;
+      0.0
     } else {
-      return x;
+      x
     }
   }
 
+  // ln(1 + e^x) without overflowing for large x
+  pub fn softplus(x: f64) -> f64 {
+    x.max(0.0) + (-x.abs()).exp().ln_1p()
+  }
+
+  // Tanh approximation of x * Phi(x)
+  pub fn gelu(x: f64) -> f64 {
+    0.5 * x * (1.0 + (GELU_C * (x + GELU_A * x.powi(3))).tanh())
+  }
+
+  pub fn hard_sigmoid(x: f64) -> f64 {
+    (x / 6.0 + 0.5).clamp(0.0, 1.0)
+  }
+
+  // Normalizes every row (sample) on its own. The row max is subtracted first so `exp` can not overflow.
   pub fn softmax(y: &Array<f64, Dim<[usize; 2]>>) -> Array<f64, Dim<[usize; 2]>> {
-    let exp = y.mapv(|yi| {
-      2.71828_f64.powf(yi)
-    });    
+    let mut out = y.to_owned();
+    out.axis_iter_mut(Axis(0)).for_each(|mut row| {
+      let max = row.fold(f64::NEG_INFINITY, |m, v| m.max(*v));
+      row.mapv_inplace(|v| (v - max).exp());
+      let sum = row.sum();
+      row /= sum;
+    });
+    out
+  }
+
+  // ln(softmax(y)) per row, without going through the probabilities
+  pub fn log_softmax(y: &Array<f64, Dim<[usize; 2]>>) -> Array<f64, Dim<[usize; 2]>> {
+    let mut out = y.to_owned();
+    out.axis_iter_mut(Axis(0)).for_each(|mut row| {
+      let max = row.fold(f64::NEG_INFINITY, |m, v| m.max(*v));
+      let log_sum = row.fold(0.0, |acc, v| acc + (v - max).exp()).ln() + max;
+      row -= log_sum;
+    });
+    out
+  }
+
+  pub fn apply(&self, z: &Array<f64, Dim<[usize; 2]>>) -> Array<f64, Dim<[usize; 2]>> {
+    use ActivationType::*;
+
+    match *self {
+      Step => z.mapv(ActivationType::step),
+      Sigmoid => z.mapv(ActivationType::sigmoid),
+      Tanh => z.mapv(ActivationType::tanh),
+      ReLU => z.mapv(|v| if v >= 0.0 { v } else { 0.0 }),
+      LeakyReLU(slope) => z.mapv(|v| if v >= 0.0 { v } else { slope * v }),
+      ELU(alpha) => z.mapv(|v| if v > 0.0 { v } else { alpha * v.exp_m1() }),
+      GELU => z.mapv(ActivationType::gelu),
+      SiLU => z.mapv(|v| v * ActivationType::sigmoid(v)),
+      Softplus => z.mapv(ActivationType::softplus),
+      Mish => z.mapv(|v| v * ActivationType::softplus(v).tanh()),
+      HardSigmoid => z.mapv(ActivationType::hard_sigmoid),
+      Linear => z.to_owned(),
+      Softmax => ActivationType::softmax(z)
+    }
+  }
+
+  // Maps the gradient w.r.t. the activation output back onto its input `z`.
+  // `out` is the value `apply` returned for `z`.
+  pub fn backward(
+    &self,
+    z: &Array<f64, Dim<[usize; 2]>>,
+    out: &Array<f64, Dim<[usize; 2]>>,
+    grad_out: &Array<f64, Dim<[usize; 2]>>,
+  ) -> Array<f64, Dim<[usize; 2]>> {
+    use ActivationType::*;
+
+    match *self {
+      Step => Array::zeros(z.raw_dim()),
+      Sigmoid => Zip::from(out).and(grad_out).map_collect(|s, g| s * (1.0 - s) * g),
+      Tanh => Zip::from(out).and(grad_out).map_collect(|t, g| (1.0 - t * t) * g),
+      ReLU => Zip::from(z).and(grad_out).map_collect(|v, g| if *v >= 0.0 { *g } else { 0.0 }),
+      LeakyReLU(slope) => Zip::from(z).and(grad_out).map_collect(|v, g| if *v >= 0.0 { *g } else { slope * g }),
+      // Below zero the slope alpha * e^z equals out + alpha
+      ELU(alpha) => Zip::from(z).and(out).and(grad_out).map_collect(|v, o, g| if *v > 0.0 { *g } else { (o + alpha) * g }),
+      GELU => Zip::from(z).and(grad_out).map_collect(|v, g| {
+        let t = (GELU_C * (v + GELU_A * v.powi(3))).tanh();
+        let dt = (1.0 - t * t) * GELU_C * (1.0 + 3.0 * GELU_A * v * v);
+        (0.5 * (1.0 + t) + 0.5 * v * dt) * g
+      }),
+      SiLU => Zip::from(z).and(grad_out).map_collect(|v, g| {
+        let s = ActivationType::sigmoid(*v);
+        (s + v * s * (1.0 - s)) * g
+      }),
+      Softplus => Zip::from(z).and(grad_out).map_collect(|v, g| ActivationType::sigmoid(*v) * g),
+      Mish => Zip::from(z).and(grad_out).map_collect(|v, g| {
+        let t = ActivationType::softplus(*v).tanh();
+        (t + v * (1.0 - t * t) * ActivationType::sigmoid(*v)) * g
+      }),
+      HardSigmoid => Zip::from(z).and(grad_out).map_collect(|v, g| if v.abs() < 3.0 { g / 6.0 } else { 0.0 }),
+      Linear => grad_out.to_owned(),
+      Softmax => {
+        // Every output of a row depends on every input of that row
+        let dot = (out * grad_out).sum_axis(Axis(1)).insert_axis(Axis(1));
+        out * &(grad_out - &dot)
+      }
+    }
+  }
+}
 
-    exp.clone() / exp.sum()
+#[cfg(test)]
+mod tests {
+  use ndarray::array;
+  use super::*;
 
-    // y.into_iter().map(|yi| {
-    //   2.71828_f64.powf(*yi) / sum 
-    // }).collect()
+  #[test]
+  fn step_fires_from_one_half() {
+    let z = array![[0.4999, 0.5, 0.75]];
+    assert_eq!(ActivationType::Step.apply(&z), array![[0.0, 1.0, 1.0]]);
+    assert_eq!(z.mapv(ActivationType::step), ActivationType::Step.apply(&z));
   }
 }
@@ -1,257 +1,490 @@
 use std::collections::{HashMap, HashSet};
+use ndarray::{Array, Dim};
 use plotters::prelude::*;
-use rand::seq::SliceRandom;
-use rayon::prelude::*;
-
-#[derive(Clone)]
-pub struct Col {
-  pub name: String,
-  pub values: Vec<String>,
+use rand::{RngCore, seq::SliceRandom};
+use crate::errors::NNErrors;
+
+// Cells read as missing, on top of empty ones
+const MISSING: [&str; 7] = ["NA", "N/A", "NaN", "nan", "null", "NULL", "?"];
+
+// Typed storage of a column, `None` marks a missing value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+  Float(Vec<Option<f64>>),
+  Int(Vec<Option<i64>>),
+  Bool(Vec<Option<bool>>),
+  // Codes index into `categories`
+  Categorical { codes: Vec<Option<usize>>, categories: Vec<String> },
+  Str(Vec<Option<String>>),
 }
 
-#[derive(Clone)]
-pub struct Row {
-  pub values: HashMap<String, String>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DType {
+  Float,
+  Int,
+  Bool,
+  Categorical,
+  Str,
 }
 
-#[derive(Clone)]
-pub struct Series  {
-  pub headers: Vec<String>,
-  pub cols: HashMap<String, Col>,
-  pub rows: HashMap<u64, Row>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Col {
+  pub name: String,
+  pub values: Values,
 }
 
-impl Series {
-  pub fn from_csv(path: &str, shuffle: bool) ->  Result<Series, Box<dyn std::error::Error>> {
-    let mut reader = csv::Reader::from_path(path)?;
+impl Col {
+  pub fn new(name: &str, values: Values) -> Col {
+    Col { name: name.to_string(), values }
+  }
 
-    let mut cols: HashMap<String, Col> = HashMap::new();
+  // Picks the narrowest type every present cell parses as: int, float, bool, then string
+  pub fn parse(name: &str, cells: &[String]) -> Col {
+    let cells: Vec<Option<&str>> = cells.iter()
+      .map(|c| c.trim())
+      .map(|c| if c.is_empty() || MISSING.contains(&c) { None } else { Some(c) })
+      .collect();
+    let present = || cells.iter().flatten();
+
+    let values = if present().all(|c| c.parse::<i64>().is_ok()) {
+      Values::Int(cells.iter().map(|c| c.and_then(|c| c.parse().ok())).collect())
+    } else if present().all(|c| c.parse::<f64>().is_ok()) {
+      Values::Float(cells.iter().map(|c| c.and_then(|c| c.parse().ok())).collect())
+    } else if present().all(|c| parse_bool(c).is_some()) {
+      Values::Bool(cells.iter().map(|c| c.and_then(parse_bool)).collect())
+    } else {
+      Values::Str(cells.iter().map(|c| c.map(|c| c.to_string())).collect())
+    };
 
-    let mut records: Vec<Vec<String>> = reader.deserialize().par_bridge().into_par_iter().map(|res| {
-      let rec: Vec<String> = res.expect("error: rec parse");
-      rec
-    }).collect();
-    
-    if shuffle {
-      records.shuffle(&mut rand::thread_rng());
+    Col::new(name, values)
+  }
+
+  pub fn len(&self) -> usize {
+    match &self.values {
+      Values::Float(v) => v.len(),
+      Values::Int(v) => v.len(),
+      Values::Bool(v) => v.len(),
+      Values::Categorical { codes, .. } => codes.len(),
+      Values::Str(v) => v.len(),
     }
+  }
 
-    let headers = reader.headers()?;
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
 
-    println!("{:?}", headers);
-    headers.to_owned().into_iter().enumerate().for_each(|(id, header)| {
-      let mut col = Col { name: header.to_string(), values: vec![] };
-      records.to_owned().into_iter().for_each(|rev| {
-        col.values.push(rev[id].to_owned());
-      });
-      cols.insert(header.to_string(), col);
-    });
+  pub fn dtype(&self) -> DType {
+    match &self.values {
+      Values::Float(_) => DType::Float,
+      Values::Int(_) => DType::Int,
+      Values::Bool(_) => DType::Bool,
+      Values::Categorical { .. } => DType::Categorical,
+      Values::Str(_) => DType::Str,
+    }
+  }
 
-    let mut rows: HashMap<u64, Row> = HashMap::new();
+  pub fn is_missing(&self, row: usize) -> bool {
+    self.get(row).is_none()
+  }
 
-    records.to_owned().into_iter().enumerate().for_each(|(id, values)| {
-      let mut row = Row {
-        values: HashMap::new()
-      };
+  pub fn missing_count(&self) -> usize {
+    (0..self.len()).filter(|row| self.is_missing(*row)).count()
+  }
 
-      values.into_iter().enumerate().for_each(|(i, val)| {
-        row.values.insert(headers[i].to_string(), val);
-      });
+  // Cell as text, `None` when missing
+  pub fn get(&self, row: usize) -> Option<String> {
+    match &self.values {
+      Values::Float(v) => v[row].map(|x| x.to_string()),
+      Values::Int(v) => v[row].map(|x| x.to_string()),
+      Values::Bool(v) => v[row].map(|x| x.to_string()),
+      Values::Categorical { codes, categories } => codes[row].map(|c| categories[c].clone()),
+      Values::Str(v) => v[row].clone(),
+    }
+  }
 
-      rows.insert(id as u64, row);
-    });
+  // Numeric view of the column, booleans count as 0 / 1
+  pub fn floats(&self) -> Result<Vec<Option<f64>>, NNErrors> {
+    match &self.values {
+      Values::Float(v) => Ok(v.clone()),
+      Values::Int(v) => Ok(v.iter().map(|x| x.map(|x| x as f64)).collect()),
+      Values::Bool(v) => Ok(v.iter().map(|x| x.map(|x| if x { 1.0 } else { 0.0 })).collect()),
+      _ => Err(NNErrors::ColumnType(self.name.clone(), "numeric")),
+    }
+  }
 
-    Ok(Series {
-      headers: headers.to_owned().into_iter().map(|h| h.to_string()).collect(),
-      cols,
-      rows
-    })
+  // Like `floats`, but every value has to be there
+  pub fn dense_floats(&self) -> Result<Vec<f64>, NNErrors> {
+    self.floats()?.into_iter().enumerate()
+      .map(|(row, x)| x.ok_or_else(|| NNErrors::MissingValue(self.name.clone(), row)))
+      .collect()
   }
 
-  pub fn scale_by(&mut self, col_name: &str, value: f64) {
-    let curr_col = self.cols.get(col_name).expect("Column does not exist");
+  // Class indices: category codes, non-negative integers (integral floats too) or 0 / 1 for booleans
+  pub fn labels(&self) -> Result<Vec<usize>, NNErrors> {
+    let labels: Vec<Option<usize>> = match &self.values {
+      Values::Categorical { codes, .. } => codes.clone(),
+      Values::Str(_) => return Err(NNErrors::ColumnType(self.name.clone(), "class label")),
+      _ => self.floats()?.into_iter()
+        .map(|x| x.map(|x| if x >= 0.0 && x.fract() == 0.0 { Ok(x as usize) } else { Err(()) }).transpose())
+        .collect::<Result<_, _>>()
+        .map_err(|_| NNErrors::ColumnType(self.name.clone(), "class label"))?,
+    };
 
-    let new_vals: Vec<String> = curr_col.values.to_owned().into_iter().enumerate().map(|(id, v)| {
-      let parsed_res = v.parse::<f64>();
-      if let Ok(par_val) = parsed_res {
-        
-        self.rows.entry(id as u64).and_modify(|r| {
-          r.values.insert(col_name.to_string(), (par_val / value).to_string());
-        });
+    labels.into_iter().enumerate()
+      .map(|(row, l)| l.ok_or_else(|| NNErrors::MissingValue(self.name.clone(), row)))
+      .collect()
+  }
 
-        return (par_val / value).to_string();
-      } else {
-        panic!("Cant parse values in current column: {}", v)
-      }
-    }).collect();
-    
-    let new_col = Col {
-      values: new_vals.to_owned(),
-      name: col_name.to_owned()
+  // Applies `f` to every present value, integer columns become float columns
+  pub fn map_floats(&mut self, f: impl Fn(f64) -> f64) -> Result<(), NNErrors> {
+    let values = match &self.values {
+      Values::Float(_) | Values::Int(_) => self.floats()?,
+      _ => return Err(NNErrors::ColumnType(self.name.clone(), "numeric")),
     };
+    self.values = Values::Float(values.into_iter().map(|x| x.map(&f)).collect());
+    Ok(())
+  }
+
+  // Keeps the given rows, in the given order
+  pub fn select(&self, rows: &[usize]) -> Col {
+    fn pick<T: Clone>(v: &[T], rows: &[usize]) -> Vec<T> {
+      rows.iter().map(|r| v[*r].clone()).collect()
+    }
 
-    self.cols.insert(col_name.to_owned(), new_col);
+    let values = match &self.values {
+      Values::Float(v) => Values::Float(pick(v, rows)),
+      Values::Int(v) => Values::Int(pick(v, rows)),
+      Values::Bool(v) => Values::Bool(pick(v, rows)),
+      Values::Categorical { codes, categories } => Values::Categorical { codes: pick(codes, rows), categories: categories.clone() },
+      Values::Str(v) => Values::Str(pick(v, rows)),
+    };
+    Col::new(&self.name, values)
   }
+}
 
-  pub fn scale_by_max(&mut self, col_name: &str) {
-    let curr_col = self.cols.get(col_name).expect("Column does not exist");
-    
-    let max_value = curr_col.values.to_owned().iter().map(|v| {
-      let parsed_res = v.parse::<f64>();
-      if let Ok(par_val) = parsed_res {
-        par_val
-      } else {
-        panic!("Cant parse values in current column: {}", v)
-      }
-    }).max_by(|a, b| a.total_cmp(b)).unwrap();
+fn parse_bool(cell: &str) -> Option<bool> {
+  match cell.to_lowercase().as_str() {
+    "true" => Some(true),
+    "false" => Some(false),
+    _ => None,
+  }
+}
 
-    (0..self.rows.len()).for_each(|id| {
-      self.rows.entry(id as u64).and_modify(|row| {
-        row.values.entry(col_name.to_owned()).and_modify(|col_val| {
-          let parsed_res = col_val.to_owned().parse::<f64>().unwrap();
+// Columnar table with typed columns
+#[derive(Debug, Clone, Default)]
+pub struct Series  {
+  // Column order
+  pub headers: Vec<String>,
+  pub cols: HashMap<String, Col>,
+}
 
-          *col_val = (parsed_res / max_value).to_string();
-        });
-      });
-    });
+impl Series {
+  // Rows are shuffled with `shuffle` when given, kept in file order otherwise
+  pub fn from_csv(path: &str, shuffle: Option<&mut dyn RngCore>) -> Result<Series, NNErrors> {
+    let mut reader = csv::Reader::from_path(path).map_err(|e| NNErrors::Csv(e.to_string()))?;
+
+    let headers: Vec<String> = reader.headers()
+      .map_err(|e| NNErrors::Csv(e.to_string()))?
+      .iter()
+      .map(|h| h.trim_start_matches('\u{feff}').trim().to_string())
+      .collect();
+
+    let mut records: Vec<Vec<String>> = reader.deserialize()
+      .collect::<Result<_, _>>()
+      .map_err(|e| NNErrors::Csv(e.to_string()))?;
+
+    if let Some(rng) = shuffle {
+      records.shuffle(rng);
+    }
 
-    let new_vals: Vec<String> = curr_col.values.to_owned().into_par_iter().map(|v| {
-      let parsed_res = v.parse::<f64>().unwrap();
-      return (parsed_res / max_value).to_string();
+    let cols = headers.iter().enumerate().map(|(id, header)| {
+      let cells: Vec<String> = records.iter().map(|rec| rec[id].to_owned()).collect();
+      Col::parse(header, &cells)
     }).collect();
-  
-    self.cols.entry(col_name.to_owned()).and_modify(|col| {
-      col.values = new_vals;
-    });
+
+    Series::from_cols(cols)
   }
 
-  pub fn replace_with(&mut self, col_name: &str, old_vals: Vec<String>, new_vals: Vec<String>) {
-    let col = self.cols.get_mut(col_name).unwrap();
+  pub fn from_cols(cols: Vec<Col>) -> Result<Series, NNErrors> {
+    let mut series = Series::default();
+    for col in cols {
+      series.push_col(col)?;
+    }
+    Ok(series)
+  }
 
-    old_vals.to_owned().into_iter().enumerate().for_each(|(i, old)| {
-      col.values = col.values.to_owned().into_par_iter().map(|v| {
-        if v == old {
-          return new_vals[i].to_string()
-        }
-        return v.to_string();
-      }).collect();
-    });
+  // Adds a column after the last one, replacing any column of the same name
+  pub fn push_col(&mut self, col: Col) -> Result<(), NNErrors> {
+    let n_rows = self.n_rows();
+    if !self.headers.is_empty() && col.len() != n_rows {
+      return Err(NNErrors::ColumnLength(col.name.clone(), col.len(), n_rows));
+    }
+
+    if !self.cols.contains_key(&col.name) {
+      self.headers.push(col.name.clone());
+    }
+    self.cols.insert(col.name.clone(), col);
+    Ok(())
   }
 
-  pub fn drop_col(&mut self, col_name: &str) -> Vec<String> {
-    let droped_col = &self.cols.get(col_name).unwrap().values.to_owned();
-    self.headers = self.headers.to_owned().into_iter().filter(|col| *col != col_name).collect();
-    self.cols.remove(col_name);
-    self.rows = (&self.rows).into_iter().map(|(id, row)| {
-      
-      let new_row = Row {
-        values: row.clone().values.iter().filter(|(col, _)| col_name != *col).map(|(s1, s2)| (s1.to_string(), s2.to_string())).collect()
-      };
+  pub fn n_rows(&self) -> usize {
+    self.headers.first().map_or(0, |h| self.cols[h].len())
+  }
 
-      return (
-        *id,
-        new_row
-      );
-    }).collect();
+  pub fn col(&self, col_name: &str) -> Result<&Col, NNErrors> {
+    self.cols.get(col_name).ok_or_else(|| NNErrors::NoColumn(col_name.to_string()))
+  }
 
-    droped_col.to_owned()
+  pub fn col_mut(&mut self, col_name: &str) -> Result<&mut Col, NNErrors> {
+    self.cols.get_mut(col_name).ok_or_else(|| NNErrors::NoColumn(col_name.to_string()))
   }
 
-  pub fn to_vecs(&self) -> Vec<Vec<f64>> {
-    (0..self.rows.len()).map(|id| {
-      let row = &self.rows.get(&(id as u64)).unwrap();
-      let values: Vec<f64> = self.headers.iter().map(|header| row.values.get(header).unwrap().parse::<f64>().expect("Error to parse value")).collect();
+  // Keeps the given rows of every column, in the given order
+  pub fn select_rows(&self, rows: &[usize]) -> Series {
+    Series {
+      headers: self.headers.clone(),
+      cols: self.cols.iter().map(|(name, col)| (name.clone(), col.select(rows))).collect(),
+    }
+  }
 
-      values
-    }).collect()
+  pub fn scale_by(&mut self, col_name: &str, value: f64) -> Result<(), NNErrors> {
+    self.col_mut(col_name)?.map_floats(|x| x / value)
   }
 
-  pub fn batchise(data: Vec<Vec<f64>>, batch_size: usize) -> Vec<Vec<Vec<f64>>> {
-    data.chunks(batch_size).map(|chunk| chunk.to_vec()).collect()
+  pub fn scale_by_max(&mut self, col_name: &str) -> Result<(), NNErrors> {
+    let max_value = self.max_by(col_name)?;
+    self.scale_by(col_name, max_value)
+  }
+
+  pub fn sub_by(&mut self, col_name: &str, value: f64) -> Result<(), NNErrors> {
+    self.col_mut(col_name)?.map_floats(|x| x - value)
+  }
+
+  // Replaces every `old_vals[i]` with `new_vals[i]` in a string or categorical column
+  pub fn replace_with(&mut self, col_name: &str, old_vals: Vec<String>, new_vals: Vec<String>) -> Result<(), NNErrors> {
+    if old_vals.len() != new_vals.len() {
+      return Err(NNErrors::ReplaceMismatch(old_vals.len(), new_vals.len()));
+    }
+    let col = self.col_mut(col_name)?;
+    let replace = |v: &mut String| {
+      if let Some(i) = old_vals.iter().position(|old| old == v) {
+        *v = new_vals[i].clone();
+      }
+    };
+
+    match &mut col.values {
+      Values::Str(values) => values.iter_mut().flatten().for_each(replace),
+      Values::Categorical { categories, .. } => {
+        categories.iter_mut().for_each(replace);
+        // Replaced categories may now collide, rebuilding merges them
+        *col = categorical(&col.name, (0..col.len()).map(|row| col.get(row)).collect());
+      }
+      _ => return Err(NNErrors::ColumnType(col.name.clone(), "string")),
+    }
+    Ok(())
   }
 
-  pub fn max_by(&self, col_name: &str) -> f64 {
-    self.cols.get(col_name).unwrap().values.iter().map(|val| val.parse::<f64>().unwrap()).max_by(|a, b| a.total_cmp(b)).unwrap()
+  // Turns a column into a categorical one, categories are sorted
+  pub fn to_categorical(&mut self, col_name: &str) -> Result<(), NNErrors> {
+    let col = self.col_mut(col_name)?;
+    *col = categorical(&col.name, (0..col.len()).map(|row| col.get(row)).collect());
+    Ok(())
   }
 
-  pub fn min_by(&self, col_name: &str) -> f64 {
-    self.cols.get(col_name).unwrap().values.iter().map(|val| val.parse::<f64>().unwrap()).min_by(|a, b| a.total_cmp(b)).unwrap()
+  pub fn drop_col(&mut self, col_name: &str) -> Result<Col, NNErrors> {
+    let col = self.cols.remove(col_name).ok_or_else(|| NNErrors::NoColumn(col_name.to_string()))?;
+    self.headers.retain(|h| h != col_name);
+    Ok(col)
   }
 
-  pub fn mean_by(&self, col_name: &str) -> f64 {
-    self.cols.get(col_name).unwrap().values.iter().map(|val| val.parse::<f64>().unwrap()).sum::<f64>() / self.cols.get(col_name).unwrap().values.len() as f64
+  // Rows of every column as numbers, fails on non-numeric columns and missing values
+  pub fn to_vecs(&self) -> Result<Vec<Vec<f64>>, NNErrors> {
+    let cols = self.headers.iter()
+      .map(|h| self.cols[h].dense_floats())
+      .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((0..self.n_rows()).map(|row| cols.iter().map(|col| col[row]).collect()).collect())
   }
 
-  pub fn std_by(&self, col_name: &str) -> f64 {
-    let mean = self.mean_by(col_name);
+  pub fn to_array(&self) -> Result<Array<f64, Dim<[usize; 2]>>, NNErrors> {
+    let data = self.to_vecs()?.concat();
+    Ok(Array::from_shape_vec((self.n_rows(), self.headers.len()), data).expect("rows have one value per column"))
+  }
 
-    let sum: f64 = self.cols.get(col_name).unwrap().values.iter().map(|val| {
-      let x = val.parse::<f64>().unwrap();
-      
-      (x - mean).powf(2.0)
-    }).sum();
+  pub fn batchise(data: Vec<Vec<f64>>, batch_size: usize) -> Vec<Vec<Vec<f64>>> {
+    data.chunks(batch_size).map(|chunk| chunk.to_vec()).collect()
+  }
+
+  // Statistics skip missing values and are NaN when nothing is left
+  pub fn max_by(&self, col_name: &str) -> Result<f64, NNErrors> {
+    Ok(self.present(col_name)?.into_iter().reduce(f64::max).unwrap_or(f64::NAN))
+  }
 
-    (sum / self.cols.get(col_name).unwrap().values.len() as f64).sqrt()
+  pub fn min_by(&self, col_name: &str) -> Result<f64, NNErrors> {
+    Ok(self.present(col_name)?.into_iter().reduce(f64::min).unwrap_or(f64::NAN))
   }
 
-  pub fn sub_by(&mut self, col_name: &str, value: f64) {
-    let curr_col = self.cols.get(col_name).expect("Column does not exist");
+  pub fn mean_by(&self, col_name: &str) -> Result<f64, NNErrors> {
+    let values = self.present(col_name)?;
+    Ok(values.iter().sum::<f64>() / values.len() as f64)
+  }
 
-    let new_vals: Vec<String> = curr_col.values.to_owned().into_iter().enumerate().map(|(id, v)| {
-      let parsed_res = v.parse::<f64>();
-      if let Ok(par_val) = parsed_res {
-        
-        self.rows.entry(id as u64).and_modify(|r| {
-          r.values.insert(col_name.to_string(), (par_val - value).to_string());
-        });
+  pub fn std_by(&self, col_name: &str) -> Result<f64, NNErrors> {
+    let mean = self.mean_by(col_name)?;
+    let values = self.present(col_name)?;
+    let sum: f64 = values.iter().map(|x| (x - mean).powi(2)).sum();
 
-        return (par_val - value).to_string();
-      } else {
-        panic!("Cant parse values in current column: {}", v)
-      }
-    }).collect();
-    
-    let new_col = Col {
-      values: new_vals.to_owned(),
-      name: col_name.to_owned()
-    };
+    Ok((sum / values.len() as f64).sqrt())
+  }
 
-    self.cols.insert(col_name.to_owned(), new_col);
+  fn present(&self, col_name: &str) -> Result<Vec<f64>, NNErrors> {
+    Ok(self.col(col_name)?.floats()?.into_iter().flatten().collect())
   }
 
-  pub fn draw_col(&self, col_name: &str) {
+  pub fn draw_col(&self, col_name: &str) -> Result<(), NNErrors> {
     let img_name = &format!("graphs/{col_name}.png");
+    let plot_err = |e: &dyn std::error::Error| NNErrors::Plot(e.to_string());
+
+    let vals = self.present(col_name)?;
 
-    let vals: Vec<f64> = self.cols.get(col_name).unwrap().values.to_owned().into_iter().map(|val| val.parse().unwrap()).collect();
-    
     let root_area = BitMapBackend::new(img_name, (1200, 600)).into_drawing_area();
-    
-    root_area.fill(&WHITE).unwrap();
+
+    root_area.fill(&WHITE).map_err(|e| plot_err(&e))?;
 
     let mut ctx = ChartBuilder::on(&root_area)
       .set_label_area_size(LabelAreaPosition::Left, 40)
       .set_label_area_size(LabelAreaPosition::Bottom, 40)
       .caption("Loss", ("sans-serif", 40))
       .build_cartesian_2d(-0..(vals.len() + 20) as i32, -2.0..10.0)
-      .unwrap();
-      
-      ctx.configure_mesh().draw().unwrap();
-      
+      .map_err(|e| plot_err(&e))?;
+
+      ctx.configure_mesh().draw().map_err(|e| plot_err(&e))?;
+
       let series_err = LineSeries::new(
         vals.iter().enumerate().map(|(i, v)| {
-          return (i as i32, *v);
+          (i as i32, *v)
         }).collect::<Vec<(i32, f64)>>(),
         &RED
       );
 
-      ctx.draw_series(series_err).unwrap();
+      ctx.draw_series(series_err).map_err(|e| plot_err(&e))?;
+      Ok(())
+  }
+
+  // Distinct present values as text, sorted by value for numeric columns and as text otherwise
+  pub fn unique_in_col(&self, col_name: &str) -> Result<Vec<String>, NNErrors> {
+    let col = self.col(col_name)?;
+    let mut unique: Vec<String> = (0..col.len())
+      .filter_map(|row| col.get(row))
+      .collect::<HashSet<String>>()
+      .into_iter()
+      .collect();
+
+    match col.dtype() {
+      DType::Float | DType::Int => unique.sort_by(|a, b| {
+        a.parse::<f64>().unwrap_or(f64::NAN).total_cmp(&b.parse::<f64>().unwrap_or(f64::NAN))
+      }),
+      _ => unique.sort(),
+    }
+    Ok(unique)
+  }
+}
+
+fn categorical(name: &str, cells: Vec<Option<String>>) -> Col {
+  let mut categories: Vec<String> = cells.iter().flatten().cloned().collect::<HashSet<_>>().into_iter().collect();
+  categories.sort();
+
+  let index: HashMap<&String, usize> = categories.iter().enumerate().map(|(i, c)| (c, i)).collect();
+  let codes = cells.iter().map(|c| c.as_ref().map(|c| index[c])).collect();
+
+  Col::new(name, Values::Categorical { codes, categories })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cells(cells: &[&str]) -> Vec<String> {
+    cells.iter().map(|c| c.to_string()).collect()
+  }
+
+  fn texts(col: &Col) -> Vec<Option<String>> {
+    (0..col.len()).map(|row| col.get(row)).collect()
+  }
+
+  fn colours() -> Series {
+    let colour = Col::parse("colour", &cells(&["red", "blue", "", "red", "green"]));
+    let size = Col::parse("size", &cells(&["1", "2", "3", "4", "5"]));
+    Series::from_cols(vec![colour, size]).unwrap()
+  }
+
+  #[test]
+  fn parse_infers_narrowest_type() {
+    assert_eq!(Col::parse("a", &cells(&["1", "-2", "30"])).values, Values::Int(vec![Some(1), Some(-2), Some(30)]));
+    assert_eq!(Col::parse("a", &cells(&["1", "2.5", "1e3"])).values, Values::Float(vec![Some(1.0), Some(2.5), Some(1000.0)]));
+    assert_eq!(Col::parse("a", &cells(&["true", "False", "TRUE"])).values, Values::Bool(vec![Some(true), Some(false), Some(true)]));
+    assert_eq!(Col::parse("a", &cells(&["1", "x"])).values, Values::Str(vec![Some("1".to_string()), Some("x".to_string())]));
+    assert_eq!(Col::parse("a", &cells(&["true", "1"])).dtype(), DType::Str);
+  }
+
+  #[test]
+  fn missing_values() {
+    let col = Col::parse("a", &cells(&["1.5", "", "NA", " ? ", "null", "NaN", "2"]));
+    assert_eq!(col.dtype(), DType::Float);
+    assert_eq!(col.missing_count(), 5);
+    assert!(col.is_missing(1) && !col.is_missing(6));
+    assert!(matches!(col.dense_floats(), Err(NNErrors::MissingValue(_, 1))));
+
+    // Only missing cells still make an (int) column
+    let empty = Col::parse("b", &cells(&["", "N/A"]));
+    assert_eq!(empty.missing_count(), 2);
+    assert_eq!(empty.dtype(), DType::Int);
   }
 
-  pub fn unique_in_col(&self, col_name: &str) -> Vec<String> {
-    self.cols.get(col_name).unwrap().values.iter()
-    .map(|v| v.to_string())
-    .collect::<HashSet<String>>()
-    .into_iter()
-    .collect()
+  #[test]
+  fn categorical_codes_follow_sorted_categories() {
+    let mut series = colours();
+    series.to_categorical("colour").unwrap();
+    let colour = series.col("colour").unwrap();
+    assert_eq!(colour.values, Values::Categorical {
+      codes: vec![Some(2), Some(0), None, Some(2), Some(1)],
+      categories: cells(&["blue", "green", "red"]),
+    });
+    assert!(matches!(colour.labels(), Err(NNErrors::MissingValue(_, 2))));
+
+    let labels = Col::parse("y", &cells(&["2", "0", "1"])).labels().unwrap();
+    assert_eq!(labels, vec![2, 0, 1]);
+    assert_eq!(Col::parse("y", &cells(&["true", "false"])).labels().unwrap(), vec![1, 0]);
+    assert!(matches!(Col::parse("y", &cells(&["0.5"])).labels(), Err(NNErrors::ColumnType(..))));
+    assert!(matches!(Col::parse("y", &cells(&["-1"])).labels(), Err(NNErrors::ColumnType(..))));
+  }
+
+  #[test]
+  fn replace_with() {
+    let mut series = colours();
+    series.to_categorical("colour").unwrap();
+    series.replace_with("colour", cells(&["green"]), cells(&["red"])).unwrap();
+    let colour = series.col("colour").unwrap();
+    assert_eq!(texts(colour), vec![Some("red".into()), Some("blue".into()), None, Some("red".into()), Some("red".into())]);
+    assert!(matches!(&colour.values, Values::Categorical { categories, .. } if *categories == cells(&["blue", "red"])));
+
+    let before = texts(series.col("colour").unwrap());
+    let err = series.replace_with("colour", cells(&["red", "blue"]), cells(&["x"]));
+    assert!(matches!(err, Err(NNErrors::ReplaceMismatch(2, 1))));
+    assert_eq!(texts(series.col("colour").unwrap()), before);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn errors_name_the_column() {
+    let mut series = colours();
+    assert!(matches!(series.col("weight"), Err(NNErrors::NoColumn(name)) if name == "weight"));
+    assert!(matches!(series.mean_by("weight"), Err(NNErrors::NoColumn(_))));
+    assert!(matches!(series.drop_col("weight"), Err(NNErrors::NoColumn(_))));
+    assert!(matches!(series.scale_by("colour", 2.0), Err(NNErrors::ColumnType(name, "numeric")) if name == "colour"));
+    assert!(matches!(series.replace_with("size", cells(&["1"]), cells(&["2"])), Err(NNErrors::ColumnType(_, "string"))));
+    assert!(matches!(series.to_array(), Err(NNErrors::ColumnType(..))));
+
+    let short = Col::parse("short", &cells(&["1"]));
+    assert!(matches!(series.push_col(short), Err(NNErrors::ColumnLength(_, 1, 5))));
+  }
+}
@@ -4,7 +4,7 @@ use glutin_window::GlutinWindow;
 use graphics::color::{GREEN, YELLOW};
 use opengl_graphics::GlGraphics;
 use piston::{WindowSettings, EventSettings, Events, RenderEvent, EventLoop, Event};
-use rand::Rng;
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
 
 use self::entity::{EntityType, Snake, Entity, Direction, FieldInfo};
 
@@ -14,13 +14,15 @@ pub struct Env {
     pub entity_type: EntityType,
     pub obj: Snake,
     pub food: [i32; 2],
-    step_ratio: f64,
     size: [u32; 2],
     gl: GlGraphics,
+    // Places the food
+    rng: StdRng,
 }
 
 impl Env {
-  pub fn new(size: [u32; 2], step_ratio: f64, entity_type: EntityType) -> Env {
+  // Food placement is driven by a generator seeded from `rng`
+  pub fn new(size: [u32; 2], step_ratio: f64, entity_type: EntityType, rng: &mut dyn RngCore) -> Env {
 
     let window: GlutinWindow = WindowSettings::new("snake env", size)
       .exit_on_esc(true)
@@ -31,10 +33,11 @@ impl Env {
 
     let gl = GlGraphics::new(opengl_graphics::OpenGL::V3_2);
 
-    let food_x = rand::thread_rng().gen_range(0..size[0]) as i32;
-    let food_y = rand::thread_rng().gen_range(0..size[1]) as i32;
+    let mut rng = StdRng::seed_from_u64(rng.next_u64());
+    let food_x = rng.gen_range(0..size[0]) as i32;
+    let food_y = rng.gen_range(0..size[1]) as i32;
 
-    Env { window, events, entity_type, size, step_ratio, obj: Snake::new(step_ratio), gl, food: [food_x, food_y] }
+    Env { window, events, entity_type, size, obj: Snake::new(step_ratio), gl, food: [food_x, food_y], rng }
   }
 
   pub fn render(&mut self, e: Event) {
@@ -64,8 +67,8 @@ impl Env {
 
     if self.obj.pos_x == self.food[0] && self.obj.pos_y == self.food[1] {
       reward += 5.0;
-      self.food[0] = rand::thread_rng().gen_range(0..self.size[0]) as i32;
-      self.food[1] = rand::thread_rng().gen_range(0..self.size[1]) as i32;
+      self.food[0] = self.rng.gen_range(0..self.size[0]) as i32;
+      self.food[1] = self.rng.gen_range(0..self.size[1]) as i32;
     }
 
     let curr_dir = match action.clone() {
@@ -3,5 +3,53 @@ use thiserror::Error;
 #[derive(Debug, Error)]
 pub enum NNErrors {
   #[error("Activation not eqal")]
-  NotEqActivation
-}
\ No newline at end of file
+  NotEqActivation,
+  #[error("Unknown layer kind: {0}")]
+  UnknownLayer(String),
+  #[error("No layer named {0}")]
+  NoLayer(String),
+  #[error("Bad layer config: {0}")]
+  LayerConfig(String),
+  #[error("Parameters do not fit layer {0}")]
+  ParamMismatch(String),
+  #[error("No column named {0}")]
+  NoColumn(String),
+  #[error("Column {0} does not hold {1} values")]
+  ColumnType(String, &'static str),
+  #[error("Column {0} is missing a value at row {1}")]
+  MissingValue(String, usize),
+  #[error("Column {0} has {1} rows, expected {2}")]
+  ColumnLength(String, usize, usize),
+  #[error("{0} values to replace but {1} replacements")]
+  ReplaceMismatch(usize, usize),
+  #[error("Column {0} has no category {1}")]
+  UnknownCategory(String, String),
+  #[error("{0} is not fitted")]
+  NotFitted(&'static str),
+  #[error("Layer {0} takes samples of shape {1:?}, got {2:?}")]
+  InputShape(String, Vec<usize>, Vec<usize>),
+  #[error("Bad split: {0}")]
+  BadSplit(String),
+  #[error("Shape {0:?} does not match {1:?}")]
+  ShapeMismatch(Vec<usize>, Vec<usize>),
+  #[error("CSV: {0}")]
+  Csv(String),
+  #[error("Plot: {0}")]
+  Plot(String),
+  #[error("Inputs have {0} rows but targets have {1}")]
+  RowMismatch(usize, usize),
+  #[error("Bad saved network: {0}")]
+  Format(String),
+  #[error("Bad hyperparameter: {0}")]
+  Hyperparameter(String),
+  #[error("Metric undefined: {0}")]
+  UndefinedMetric(String),
+  #[error("Unsupported saved network version {0}")]
+  UnsupportedVersion(u32),
+  #[error("Saved optimizer state is for {0}")]
+  OptimizerMismatch(String),
+  #[error("ONNX: {0}")]
+  Onnx(String),
+  #[error("IO error: {0}")]
+  Io(#[from] std::io::Error),
+}
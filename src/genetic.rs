@@ -0,0 +1,84 @@
+use rand::{Rng, RngCore};
+
+use crate::network::Network;
+
+// -- CONSTANTS -- //
+
+pub const POP_SIZE: usize = 20;
+pub const MAX_GEN: u32 = 100;
+pub const P_CROSSOW: f64 = 0.5;
+pub const P_MUTATION: f64 = 0.15;
+
+pub fn all_unique<T>(vals: &[T]) -> bool where T: PartialEq + PartialOrd + Clone {
+    let mut prev_value = vals[0].clone();
+    let mut uniq = true;
+    for i in 1..vals.len() {
+        for val in vals.iter().skip(1) {
+            uniq = prev_value == *val;
+        }
+        prev_value = vals[i].clone();
+    }
+
+    uniq
+}
+
+pub fn toutnament(vals: Vec<f64>, n_leaders: usize, p_len: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+    let mut out_ids = Vec::new();
+    for _ in 0..p_len {
+        let mut ids = vec![0; n_leaders];
+
+        let mut i = 0;
+        while all_unique::<usize>(&ids)  {
+            ids[i] = rng.gen_range(0..vals.len());
+
+            if i == n_leaders - 1 {
+                i = 0;
+            } else {
+                i += 1;
+            }
+        }
+        let best = ids.clone().into_iter().max_by(|id1, id2| {
+            vals[*id1].partial_cmp(&vals[*id2]).unwrap()
+        }).unwrap();
+
+        out_ids.push(best);
+    }
+
+    out_ids
+}
+
+pub fn mutate_weigths(ws: &mut Vec<(String, usize, f64)>, rng: &mut dyn RngCore) {
+    *ws = ws.clone().into_iter().map(|w| {
+        let p = rng.gen_range(0.0..=100.0) / 100.0;
+        let mut w = w.clone();
+        if p <= P_MUTATION {
+            let v = rng.gen_range(-50.0..=200.0) / 100.0;
+            w.2 += v;
+        }
+        w
+    }).collect::<Vec<(String, usize, f64)>>();
+}
+
+pub fn crossover(par1: &Network, par2: &Network, rng: &mut dyn RngCore) -> (Network, Network) {
+
+    let mut ch1 = par1.clone();
+    let mut ch2 = par2.clone();
+
+    let par1_ws = par1.weights_to_vec();
+    let par2_ws = par2.weights_to_vec();
+
+    let rand_id = rng.gen_range(2..par1_ws.len() - 3);
+
+    let mut gen1 = par1_ws[0..rand_id].to_vec().clone();
+    let gen2 = par2_ws[rand_id..par1_ws.len()].to_vec().clone();
+    gen1.extend(gen2);
+    ch1.import_ws(gen1);
+
+
+    let mut gen2 = par2_ws[0..rand_id].to_vec().clone();
+    let gen1 = par1_ws[rand_id..par1_ws.len()].to_vec().clone();
+    gen2.extend(gen1);
+    ch2.import_ws(gen2);
+
+    (ch1, ch2)
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+use std::sync::Arc;
+use ndarray::{Array, Dim};
+use rand::{Rng, RngCore};
+use rand_distr::StandardNormal;
+use crate::activation::ActivationType;
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+// Custom scheme, called once per weight with (fan_in, fan_out, rng)
+pub type InitFn = dyn Fn(usize, usize, &mut dyn RngCore) -> f64 + Send + Sync;
+
+// How the weights of a layer are drawn. `fan_in` / `fan_out` are the number of inputs feeding
+// one output and of outputs fed by one input (times the window area for convolutions).
+#[derive(Clone)]
+pub enum Initializer {
+  GlorotUniform,
+  GlorotNormal,
+  HeUniform,
+  HeNormal,
+  LeCunUniform,
+  LeCunNormal,
+  // Orthonormal rows (or columns) scaled by the gain
+  Orthogonal(f64),
+  // Uniform in [-limit, limit]
+  Uniform(f64),
+  Zeros,
+  Constant(f64),
+  Custom(Arc<InitFn>),
+}
+
+impl fmt::Debug for Initializer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use Initializer::*;
+
+    match self {
+      GlorotUniform => write!(f, "GlorotUniform"),
+      GlorotNormal => write!(f, "GlorotNormal"),
+      HeUniform => write!(f, "HeUniform"),
+      HeNormal => write!(f, "HeNormal"),
+      LeCunUniform => write!(f, "LeCunUniform"),
+      LeCunNormal => write!(f, "LeCunNormal"),
+      Orthogonal(gain) => write!(f, "Orthogonal({gain})"),
+      Uniform(limit) => write!(f, "Uniform({limit})"),
+      Zeros => write!(f, "Zeros"),
+      Constant(value) => write!(f, "Constant({value})"),
+      Custom(_) => write!(f, "Custom"),
+    }
+  }
+}
+
+impl Initializer {
+  pub fn custom(f: impl Fn(usize, usize, &mut dyn RngCore) -> f64 + Send + Sync + 'static) -> Initializer {
+    Initializer::Custom(Arc::new(f))
+  }
+
+  // He for the ReLU family, Glorot for everything else
+  pub fn for_activation(activation: ActivationType) -> Initializer {
+    use ActivationType::*;
+
+    match activation {
+      ReLU | LeakyReLU(_) | ELU(_) | GELU | SiLU | Mish => Initializer::HeNormal,
+      _ => Initializer::GlorotUniform,
+    }
+  }
+
+  pub fn matrix(&self, shape: (usize, usize), fan_in: usize, fan_out: usize, rng: &mut dyn RngCore) -> Matrix {
+    use Initializer::*;
+
+    let (fan_in, fan_out) = (fan_in.max(1) as f64, fan_out.max(1) as f64);
+    match self {
+      GlorotUniform => uniform(shape, (6.0 / (fan_in + fan_out)).sqrt(), rng),
+      HeUniform => uniform(shape, (6.0 / fan_in).sqrt(), rng),
+      LeCunUniform => uniform(shape, (3.0 / fan_in).sqrt(), rng),
+      Uniform(limit) => uniform(shape, *limit, rng),
+      GlorotNormal => normal(shape, (2.0 / (fan_in + fan_out)).sqrt(), rng),
+      HeNormal => normal(shape, (2.0 / fan_in).sqrt(), rng),
+      LeCunNormal => normal(shape, (1.0 / fan_in).sqrt(), rng),
+      Orthogonal(gain) => orthogonal(shape, rng) * *gain,
+      Zeros => Array::zeros(shape),
+      Constant(value) => Array::from_elem(shape, *value),
+      Custom(f) => Array::from_shape_simple_fn(shape, || f(fan_in as usize, fan_out as usize, rng)),
+    }
+  }
+}
+
+fn uniform(shape: (usize, usize), limit: f64, rng: &mut dyn RngCore) -> Matrix {
+  Array::from_shape_simple_fn(shape, || rng.gen_range(-limit..=limit))
+}
+
+fn normal(shape: (usize, usize), std: f64, rng: &mut dyn RngCore) -> Matrix {
+  Array::from_shape_simple_fn(shape, || std * rng.sample::<f64, _>(StandardNormal))
+}
+
+// Gram-Schmidt on the columns of a gaussian (long side x short side) matrix
+fn orthogonal(shape: (usize, usize), rng: &mut dyn RngCore) -> Matrix {
+  let (rows, cols) = shape;
+  let (long, short) = (rows.max(cols), rows.min(cols));
+  let mut q = normal((long, short), 1.0, rng);
+
+  for j in 0..short {
+    // Second pass keeps the columns orthogonal despite rounding
+    for _ in 0..2 {
+      for k in 0..j {
+        let proj = q.column(k).dot(&q.column(j));
+        let prev = q.column(k).to_owned();
+        q.column_mut(j).scaled_add(-proj, &prev);
+      }
+    }
+    let norm = q.column(j).dot(&q.column(j)).sqrt();
+    q.column_mut(j).mapv_inplace(|v| v / norm);
+  }
+
+  if rows >= cols { q } else { q.reversed_axes().as_standard_layout().to_owned() }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, rngs::StdRng};
+  use super::*;
+
+  // 200 x 300 weights with fan-in 300 and fan-out 200, like a dense layer of 300 inputs
+  fn draw(init: &Initializer, seed: u64) -> Matrix {
+    init.matrix((200, 300), 300, 200, &mut StdRng::seed_from_u64(seed))
+  }
+
+  fn std(weights: &Matrix) -> f64 {
+    weights.std(0.0)
+  }
+
+  #[test]
+  fn uniform_schemes_fill_their_bounds() {
+    for (init, limit) in [
+      (Initializer::GlorotUniform, (6.0f64 / 500.0).sqrt()),
+      (Initializer::HeUniform, (6.0f64 / 300.0).sqrt()),
+      (Initializer::LeCunUniform, (3.0f64 / 300.0).sqrt()),
+      (Initializer::Uniform(0.3), 0.3),
+    ] {
+      let weights = draw(&init, 1);
+      let max = weights.fold(0.0f64, |m, w| m.max(w.abs()));
+      assert!(max <= limit && max > 0.99 * limit, "{init:?}: {max} vs {limit}");
+      // Uniform in [-a, a] has standard deviation a / sqrt(3)
+      assert!((std(&weights) / (limit / 3f64.sqrt()) - 1.0).abs() < 0.02, "{init:?}");
+    }
+  }
+
+  #[test]
+  fn normal_schemes_scale_by_fan() {
+    for (init, expected) in [
+      (Initializer::GlorotNormal, (2.0f64 / 500.0).sqrt()),
+      (Initializer::HeNormal, (2.0f64 / 300.0).sqrt()),
+      (Initializer::LeCunNormal, (1.0f64 / 300.0).sqrt()),
+    ] {
+      let weights = draw(&init, 2);
+      assert!((std(&weights) / expected - 1.0).abs() < 0.02, "{init:?}: {} vs {expected}", std(&weights));
+      assert!(weights.mean().unwrap().abs() < 0.05 * expected);
+    }
+  }
+
+  #[test]
+  fn orthogonal_rows_or_columns() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for shape in [(4, 9), (9, 4), (6, 6)] {
+      let w = Initializer::Orthogonal(2.0).matrix(shape, shape.1, shape.0, &mut rng);
+      assert_eq!(w.dim(), shape);
+      // The short side comes out orthonormal, scaled by the gain
+      let gram = if shape.0 <= shape.1 { w.dot(&w.t()) } else { w.t().dot(&w) };
+      let identity = Matrix::eye(shape.0.min(shape.1)) * 4.0;
+      assert!(gram.iter().zip(identity.iter()).all(|(g, i)| (g - i).abs() < 1e-10), "{shape:?}: {gram}");
+    }
+  }
+
+  #[test]
+  fn same_seed_gives_same_weights() {
+    let custom = Initializer::custom(|fan_in, _, rng| rng.gen_range(0.0..1.0) / fan_in as f64);
+    let draw = |init: &Initializer, seed| init.matrix((20, 30), 30, 20, &mut StdRng::seed_from_u64(seed));
+    for init in [Initializer::GlorotUniform, Initializer::HeNormal, Initializer::Orthogonal(1.0), custom] {
+      assert_eq!(draw(&init, 4), draw(&init, 4));
+      assert_ne!(draw(&init, 4), draw(&init, 5));
+    }
+  }
+
+  #[test]
+  fn constant_schemes() {
+    assert_eq!(draw(&Initializer::Zeros, 0), Matrix::zeros((200, 300)));
+    assert_eq!(draw(&Initializer::Constant(0.5), 0), Matrix::from_elem((200, 300), 0.5));
+    // A fan of 0 counts as 1 instead of dividing by zero
+    let weights = Initializer::HeUniform.matrix((1, 0), 0, 1, &mut StdRng::seed_from_u64(0));
+    assert_eq!(weights.dim(), (1, 0));
+    assert!(Initializer::LeCunUniform.matrix((2, 2), 0, 0, &mut StdRng::seed_from_u64(0)).iter().all(|w| w.abs() <= 3f64.sqrt()));
+  }
+}
@@ -0,0 +1,79 @@
+use ndarray::{Array, Dim};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use crate::{activation::ActivationType, errors::NNErrors, tensor::{self, Tensor}};
+use super::{Layer, check_grad, parse_config};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+// Applies an `ActivationType` to its inputs, `Softmax` normalizes along the last axis
+#[derive(Debug, Clone)]
+pub struct Activation {
+  pub name: String,
+  pub activation: ActivationType,
+  // Inputs and outputs of the last `forward` as rows along the last axis
+  z: Matrix,
+  out: Matrix,
+  shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct Config {
+  name: String,
+  activation: ActivationType,
+}
+
+impl Activation {
+  pub const KIND: &'static str = "activation";
+
+  pub fn new(name: &str, activation: ActivationType) -> Activation {
+    Activation {
+      name: name.to_owned(),
+      activation,
+      z: Array::zeros((0, 0)),
+      out: Array::zeros((0, 0)),
+      shape: Vec::new(),
+    }
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: Config = parse_config(config)?;
+    Ok(Box::new(Activation::new(&config.name, config.activation)))
+  }
+}
+
+impl Layer for Activation {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    Activation::KIND
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    tensor::reshape(&self.activation.apply(&tensor::last_axis_rows(inputs)?), inputs.shape())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    self.z = tensor::last_axis_rows(inputs)?;
+    self.out = self.activation.apply(&self.z);
+    self.shape = inputs.shape().to_vec();
+    tensor::reshape(&self.out, &self.shape)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    check_grad(&self.shape, grad_out)?;
+    let grad = self.activation.backward(&self.z, &self.out, &tensor::last_axis_rows(grad_out)?);
+    tensor::reshape(&grad, &self.shape)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "activation": self.activation })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
@@ -0,0 +1,318 @@
+use ndarray::{s, Array, ArrayViewD, Axis, Dim};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+  errors::NNErrors,
+  initializer::Initializer,
+  regularization::Regularizer,
+  tensor::{self, Tensor},
+};
+use super::{Layer, Param, check_grad, check_image, parse_config, reshape_images, to_images};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+type Vector = Array<f64, Dim<[usize; 1]>>;
+type Images = Array<f64, Dim<[usize; 4]>>;
+
+// 2D convolution over NCHW tensors with a square window.
+// The kernel is kept as a matrix of shape (out_channels, in_channels * window * window),
+// so a convolution is one matrix product against the unrolled input patches (im2col).
+// As a `Layer` it takes samples of [in_channels, height, width] (or those values flattened)
+// and returns samples of [out_channels, output height, output width].
+#[derive(Debug, Clone)]
+pub struct Conv2d {
+  pub name: String,
+  pub in_channels: usize,
+  pub out_channels: usize,
+  pub window: usize,
+  pub step: usize,
+  pub padding: usize,
+  // (height, width) of every input image
+  pub input_size: (usize, usize),
+  pub kernel: Matrix,
+  pub bias: Vector,
+  pub regularizer: Option<Regularizer>,
+  grad_kernel: Matrix,
+  grad_bias: Vector,
+  images: Images,
+  // Shape of the batch of the last `forward`, its gradient is returned in that shape
+  in_shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct Config {
+  name: String,
+  in_channels: usize,
+  out_channels: usize,
+  window: usize,
+  step: usize,
+  padding: usize,
+  input_size: (usize, usize),
+  #[serde(default)]
+  regularizer: Option<Regularizer>,
+}
+
+impl Conv2d {
+  pub const KIND: &'static str = "conv2d";
+
+  // Kernel starts at zero, see `Layer::init`. The window has to fit the padded image.
+  pub fn new(
+    name: &str,
+    in_channels: usize,
+    out_channels: usize,
+    window: usize,
+    step: usize,
+    padding: usize,
+    input_size: (usize, usize),
+  ) -> Result<Conv2d, NNErrors> {
+    if step == 0 {
+      return Err(NNErrors::LayerConfig(format!("{name} needs a step of at least 1")));
+    }
+    let (h, w) = input_size;
+    if window == 0 || window > h.min(w) + 2 * padding {
+      return Err(NNErrors::LayerConfig(format!(
+        "{name} can not slide a window of {window} over {h}x{w} images padded by {padding}"
+      )));
+    }
+
+    let kernel_shape = (out_channels, in_channels * window * window);
+
+    Ok(Conv2d {
+      name: name.to_owned(),
+      in_channels,
+      out_channels,
+      window,
+      step,
+      padding,
+      input_size,
+      kernel: Array::zeros(kernel_shape),
+      bias: Array::zeros(out_channels),
+      regularizer: None,
+      grad_kernel: Array::zeros(kernel_shape),
+      grad_bias: Array::zeros(out_channels),
+      images: Array::zeros((0, in_channels, input_size.0, input_size.1)),
+      in_shape: vec![0, in_channels, input_size.0, input_size.1],
+    })
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let c: Config = parse_config(config)?;
+    let mut conv = Conv2d::new(&c.name, c.in_channels, c.out_channels, c.window, c.step, c.padding, c.input_size)?;
+    conv.regularize(c.regularizer)?;
+    Ok(Box::new(conv))
+  }
+
+  pub fn output_size(&self) -> (usize, usize) {
+    let (h, w) = self.input_size;
+    (
+      (h + 2 * self.padding - self.window) / self.step + 1,
+      (w + 2 * self.padding - self.window) / self.step + 1,
+    )
+  }
+
+  pub fn n_input(&self) -> usize {
+    self.in_channels * self.input_size.0 * self.input_size.1
+  }
+
+  pub fn n_output(&self) -> usize {
+    let (oh, ow) = self.output_size();
+    self.out_channels * oh * ow
+  }
+
+  // Unrolls every window position into a row: (batch * oh * ow, in_channels * window * window)
+  pub fn im2col(&self, x: &Images) -> Matrix {
+    let batch = x.shape()[0];
+    let (h, w) = self.input_size;
+    let (oh, ow) = self.output_size();
+    let (k, p) = (self.window, self.padding);
+
+    let mut padded = Array::zeros((batch, self.in_channels, h + 2 * p, w + 2 * p));
+    padded.slice_mut(s![.., .., p..p + h, p..p + w]).assign(x);
+
+    let mut cols = Array::zeros((batch * oh * ow, self.in_channels * k * k));
+    for n in 0..batch {
+      for i in 0..oh {
+        for j in 0..ow {
+          let (y0, x0) = (i * self.step, j * self.step);
+          let patch = padded.slice(s![n, .., y0..y0 + k, x0..x0 + k]);
+          let mut row = cols.row_mut((n * oh + i) * ow + j);
+          row.iter_mut().zip(patch.iter()).for_each(|(c, v)| *c = *v);
+        }
+      }
+    }
+
+    cols
+  }
+
+  // Inverse of `im2col`: sums every unrolled row back onto the input positions it was read from
+  pub fn col2im(&self, cols: &Matrix, batch: usize) -> Images {
+    let (h, w) = self.input_size;
+    let (oh, ow) = self.output_size();
+    let (k, p) = (self.window, self.padding);
+
+    let mut padded = Array::zeros((batch, self.in_channels, h + 2 * p, w + 2 * p));
+    for n in 0..batch {
+      for i in 0..oh {
+        for j in 0..ow {
+          let (y0, x0) = (i * self.step, j * self.step);
+          let row = cols.row((n * oh + i) * ow + j);
+          let mut patch = padded.slice_mut(s![n, .., y0..y0 + k, x0..x0 + k]);
+          patch.iter_mut().zip(row.iter()).for_each(|(v, c)| *v += *c);
+        }
+      }
+    }
+
+    padded.slice(s![.., .., p..p + h, p..p + w]).to_owned()
+  }
+
+  pub fn convolve(&self, x: &Images) -> Images {
+    let batch = x.shape()[0];
+    let (oh, ow) = self.output_size();
+
+    let z = self.im2col(x).dot(&self.kernel.t()) + &self.bias;
+
+    z.into_shape((batch, oh, ow, self.out_channels))
+      .unwrap()
+      .permuted_axes([0, 3, 1, 2])
+      .as_standard_layout()
+      .to_owned()
+  }
+
+  // Returns the gradients w.r.t. the kernel, the bias and the input images
+  pub fn convolve_backward(&self, x: &Images, grad_out: &Images) -> (Matrix, Vector, Images) {
+    let batch = x.shape()[0];
+    let (oh, ow) = self.output_size();
+
+    let grad_z = grad_out
+      .view()
+      .permuted_axes([0, 2, 3, 1])
+      .as_standard_layout()
+      .into_shape((batch * oh * ow, self.out_channels))
+      .unwrap()
+      .to_owned();
+
+    let cols = self.im2col(x);
+    let grad_kernel = grad_z.t().dot(&cols);
+    let grad_bias = grad_z.sum_axis(Axis(0));
+    let grad_x = self.col2im(&grad_z.dot(&self.kernel), batch);
+
+    (grad_kernel, grad_bias, grad_x)
+  }
+
+  fn image_shape(&self) -> (usize, usize, usize) {
+    (self.in_channels, self.input_size.0, self.input_size.1)
+  }
+}
+
+impl Layer for Conv2d {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    Conv2d::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    let (c, h, w) = self.image_shape();
+    Some(vec![c, h, w])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    check_image(&self.name, self.image_shape(), input)?;
+    let (oh, ow) = self.output_size();
+    Ok(vec![self.out_channels, oh, ow])
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    Ok(self.convolve(&to_images(&self.name, inputs, self.image_shape())?).into_dyn())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    self.images = to_images(&self.name, inputs, self.image_shape())?;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(self.convolve(&self.images).into_dyn())
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    let (oh, ow) = self.output_size();
+    check_grad(&[self.images.shape()[0], self.out_channels, oh, ow], grad_out)?;
+    let grad_maps = reshape_images(grad_out, (self.out_channels, oh, ow))?;
+
+    let (grad_kernel, grad_bias, grad_images) = self.convolve_backward(&self.images, &grad_maps);
+    self.grad_kernel = grad_kernel;
+    if let Some(regularizer) = self.regularizer {
+      self.grad_kernel += &regularizer.grad(&self.kernel);
+    }
+    self.grad_bias = grad_bias;
+
+    tensor::reshape(&grad_images, &self.in_shape)
+  }
+
+  fn params(&self) -> Vec<ArrayViewD<'_, f64>> {
+    vec![self.kernel.view().into_dyn(), self.bias.view().into_dyn()]
+  }
+
+  fn init(&mut self, init: &Initializer, rng: &mut dyn RngCore) {
+    let area = self.window * self.window;
+    self.kernel = init.matrix(self.kernel.dim(), self.in_channels * area, self.out_channels * area, rng);
+    self.bias.fill(0.0);
+  }
+
+  fn params_mut(&mut self) -> Vec<Param<'_>> {
+    vec![
+      Param { value: self.kernel.view_mut().into_dyn(), grad: self.grad_kernel.view_mut().into_dyn() },
+      Param { value: self.bias.view_mut().into_dyn(), grad: self.grad_bias.view_mut().into_dyn() },
+    ]
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({
+      "name": self.name,
+      "in_channels": self.in_channels,
+      "out_channels": self.out_channels,
+      "window": self.window,
+      "step": self.step,
+      "padding": self.padding,
+      "input_size": self.input_size,
+      "regularizer": self.regularizer,
+    })
+  }
+
+  fn regularize(&mut self, regularizer: Option<Regularizer>) -> Result<(), NNErrors> {
+    if let Some(regularizer) = regularizer {
+      regularizer.check()?;
+    }
+    self.regularizer = regularizer;
+    Ok(())
+  }
+
+  fn penalty(&self) -> f64 {
+    self.regularizer.map_or(0.0, |r| r.penalty(&self.kernel))
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejects_windows_that_do_not_fit() {
+    assert!(matches!(Conv2d::new("c", 1, 1, 5, 1, 0, (3, 3)), Err(NNErrors::LayerConfig(_))));
+    assert!(matches!(Conv2d::new("c", 1, 1, 3, 0, 0, (3, 3)), Err(NNErrors::LayerConfig(_))));
+    assert!(matches!(Conv2d::new("c", 1, 1, 0, 1, 0, (3, 3)), Err(NNErrors::LayerConfig(_))));
+    assert_eq!(Conv2d::new("c", 1, 1, 5, 1, 1, (3, 3)).unwrap().output_size(), (1, 1));
+  }
+
+  #[test]
+  fn bad_saved_config_is_an_error() {
+    let config = json!({
+      "name": "c", "in_channels": 1, "out_channels": 1, "window": 3, "step": 0, "padding": 0, "input_size": [3, 3],
+    });
+    assert!(matches!(Conv2d::from_config(&config), Err(NNErrors::LayerConfig(_))));
+  }
+}
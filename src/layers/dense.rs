@@ -0,0 +1,164 @@
+use ndarray::{Array, ArrayViewD, Axis, Dim};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+  errors::NNErrors,
+  initializer::Initializer,
+  regularization::Regularizer,
+  tensor::{self, Tensor},
+};
+use super::{Layer, Param, parse_config};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+type Vector = Array<f64, Dim<[usize; 1]>>;
+
+// Fully connected layer: inputs.dot(weights.t()) + bias.
+// Samples whose last axis holds `n_input` values are mapped along that axis ([steps, n_input] gives
+// [steps, n_output]), other samples of `n_input` values in total are flattened first.
+#[derive(Debug, Clone)]
+pub struct Dense {
+  pub name: String,
+  pub n_input: usize,
+  pub n_output: usize,
+  // Row `o` holds the weights feeding output `o`, column `i` the ones reading input `i`
+  pub weights: Matrix,
+  pub bias: Vector,
+  pub regularizer: Option<Regularizer>,
+  grad_weights: Matrix,
+  grad_bias: Vector,
+  inputs: Matrix,
+  // Shape of the batch of the last `forward`, its gradient is returned in that shape
+  in_shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct Config {
+  name: String,
+  n_input: usize,
+  n_output: usize,
+  #[serde(default)]
+  regularizer: Option<Regularizer>,
+}
+
+impl Dense {
+  pub const KIND: &'static str = "dense";
+
+  // Weights start at zero, see `Layer::init`
+  pub fn new(name: &str, n_input: usize, n_output: usize) -> Dense {
+    Dense {
+      name: name.to_owned(),
+      n_input,
+      n_output,
+      weights: Array::zeros((n_output, n_input)),
+      bias: Array::zeros(n_output),
+      regularizer: None,
+      grad_weights: Array::zeros((n_output, n_input)),
+      grad_bias: Array::zeros(n_output),
+      inputs: Array::zeros((0, n_input)),
+      in_shape: vec![0, n_input],
+    }
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: Config = parse_config(config)?;
+    let mut dense = Dense::new(&config.name, config.n_input, config.n_output);
+    dense.regularize(config.regularizer)?;
+    Ok(Box::new(dense))
+  }
+
+  // Inputs as rows of `n_input` values and the shape of the output batch
+  fn rows(&self, inputs: &Tensor) -> Result<(Matrix, Vec<usize>), NNErrors> {
+    let batch = tensor::batch_size(inputs);
+    let out_shape = self.output_shape(tensor::sample_shape(inputs))?;
+    let rows = tensor::to_matrix(inputs, inputs.len() / self.n_input.max(1), self.n_input)?;
+    Ok((rows, tensor::batch_shape(batch, &out_shape)))
+  }
+}
+
+impl Layer for Dense {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    Dense::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(vec![self.n_input])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    match input.split_last() {
+      Some((last, rest)) if *last == self.n_input => Ok(rest.iter().copied().chain([self.n_output]).collect()),
+      _ if input.iter().product::<usize>() == self.n_input => Ok(vec![self.n_output]),
+      _ => Err(NNErrors::InputShape(self.name.clone(), vec![self.n_input], input.to_vec())),
+    }
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    let (rows, out_shape) = self.rows(inputs)?;
+    tensor::reshape(&(rows.dot(&self.weights.t()) + &self.bias), &out_shape)
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let (rows, out_shape) = self.rows(inputs)?;
+    let out = tensor::reshape(&(rows.dot(&self.weights.t()) + &self.bias), &out_shape)?;
+    self.inputs = rows;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(out)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    let grad_out = tensor::to_matrix(grad_out, self.inputs.nrows(), self.n_output)?;
+    self.grad_weights = grad_out.t().dot(&self.inputs);
+    if let Some(regularizer) = self.regularizer {
+      self.grad_weights += &regularizer.grad(&self.weights);
+    }
+    self.grad_bias = grad_out.sum_axis(Axis(0));
+
+    tensor::reshape(&grad_out.dot(&self.weights), &self.in_shape)
+  }
+
+  fn params(&self) -> Vec<ArrayViewD<'_, f64>> {
+    vec![self.weights.view().into_dyn(), self.bias.view().into_dyn()]
+  }
+
+  fn init(&mut self, init: &Initializer, rng: &mut dyn RngCore) {
+    self.weights = init.matrix((self.n_output, self.n_input), self.n_input, self.n_output, rng);
+    self.bias.fill(0.0);
+  }
+
+  fn params_mut(&mut self) -> Vec<Param<'_>> {
+    vec![
+      Param { value: self.weights.view_mut().into_dyn(), grad: self.grad_weights.view_mut().into_dyn() },
+      Param { value: self.bias.view_mut().into_dyn(), grad: self.grad_bias.view_mut().into_dyn() },
+    ]
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({
+      "name": self.name,
+      "n_input": self.n_input,
+      "n_output": self.n_output,
+      "regularizer": self.regularizer,
+    })
+  }
+
+  fn regularize(&mut self, regularizer: Option<Regularizer>) -> Result<(), NNErrors> {
+    if let Some(regularizer) = regularizer {
+      regularizer.check()?;
+    }
+    self.regularizer = regularizer;
+    Ok(())
+  }
+
+  fn penalty(&self) -> f64 {
+    self.regularizer.map_or(0.0, |r| r.penalty(&self.weights))
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
@@ -0,0 +1,112 @@
+use ndarray::Array;
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{errors::NNErrors, tensor::Tensor};
+use super::{Layer, check_grad, parse_config};
+
+// Inverted dropout: while training each unit is zeroed with probability `rate` and the kept
+// ones are scaled by 1 / (1 - rate), so the layer is a plain identity at inference time
+#[derive(Debug, Clone)]
+pub struct Dropout {
+  pub name: String,
+  pub rate: f32,
+  mask: Tensor,
+}
+
+#[derive(Deserialize)]
+struct Config {
+  name: String,
+  rate: f32,
+}
+
+impl Dropout {
+  pub const KIND: &'static str = "dropout";
+
+  // `rate` has to be in [0, 1)
+  pub fn new(name: &str, rate: f32) -> Result<Dropout, NNErrors> {
+    if !(0.0..1.0).contains(&rate) {
+      return Err(NNErrors::LayerConfig(format!("{name} needs a dropout rate in [0, 1), got {rate}")));
+    }
+
+    Ok(Dropout {
+      name: name.to_owned(),
+      rate,
+      mask: Tensor::zeros(vec![0]),
+    })
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: Config = parse_config(config)?;
+    Ok(Box::new(Dropout::new(&config.name, config.rate)?))
+  }
+}
+
+impl Layer for Dropout {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    Dropout::KIND
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    Ok(inputs.to_owned())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let keep = 1.0 - self.rate as f64;
+
+    self.mask = Array::from_shape_simple_fn(inputs.raw_dim(), || {
+      if keep >= 1.0 || rng.gen::<f64>() < keep { 1.0 / keep } else { 0.0 }
+    });
+
+    Ok(inputs * &self.mask)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    check_grad(self.mask.shape(), grad_out)?;
+    Ok(grad_out * &self.mask)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "rate": self.rate })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, rngs::StdRng};
+  use super::*;
+
+  #[test]
+  fn rejects_rates_outside_unit_interval() {
+    for rate in [-0.1, 1.0, 1.5, f32::NAN] {
+      assert!(matches!(Dropout::new("d", rate), Err(NNErrors::LayerConfig(_))));
+    }
+    assert!(Dropout::from_config(&json!({ "name": "d", "rate": -0.5 })).is_err());
+    assert!(Dropout::new("d", 0.0).is_ok());
+  }
+
+  #[test]
+  fn keeps_expected_fraction_scaled_up() {
+    let mut dropout = Dropout::new("d", 0.25).unwrap();
+    let inputs = Tensor::ones(vec![100, 100]);
+    let out = dropout.forward(&inputs, &mut StdRng::seed_from_u64(7)).unwrap();
+
+    let kept: Vec<f64> = out.iter().copied().filter(|v| *v != 0.0).collect();
+    let fraction = kept.len() as f64 / out.len() as f64;
+    assert!((fraction - 0.75).abs() < 0.02, "kept {fraction}");
+    assert!(kept.iter().all(|v| (v - 1.0 / 0.75).abs() < 1e-12));
+
+    // Same mask on the way back, nothing changes at inference
+    let grad = dropout.backward(&inputs).unwrap();
+    assert_eq!(grad, out);
+    assert_eq!(dropout.output(&inputs).unwrap(), inputs);
+  }
+}
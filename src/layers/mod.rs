@@ -0,0 +1,222 @@
+pub mod activation;
+pub mod conv;
+pub mod dense;
+pub mod dropout;
+pub mod norm;
+pub mod pool;
+pub mod reshape;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+use ndarray::{Array, ArrayD, ArrayViewD, ArrayViewMutD, Dim};
+use rand::RngCore;
+use serde::{ Deserialize, Serialize };
+use crate::{errors::NNErrors, initializer::Initializer, regularization::Regularizer, tensor::{self, Tensor}};
+
+type Images = Array<f64, Dim<[usize; 4]>>;
+
+pub use self::{
+  activation::Activation,
+  conv::Conv2d,
+  dense::Dense,
+  dropout::Dropout,
+  norm::{BatchNorm1d, LayerNorm},
+  pool::{AvgPool2d, GlobalAveragePool, MaxPool2d},
+  reshape::{Flatten, Reshape},
+};
+
+// A trainable array of a layer together with the gradient of its last backward pass
+pub struct Param<'a> {
+  pub value: ArrayViewMutD<'a, f64>,
+  pub grad: ArrayViewMutD<'a, f64>,
+}
+
+pub trait Layer: fmt::Debug + Send + Sync {
+  fn name(&self) -> &str;
+
+  // Tag the layer is saved under, `register_layer` maps it back to a builder
+  fn kind(&self) -> &'static str;
+
+  // Shape of one sample the layer was built for, `None` when it takes any shape
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    None
+  }
+
+  // Shape of one output sample for samples of shape `input`, or why the layer can not take them
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    Ok(input.to_vec())
+  }
+
+  // Inference pass over a batch along the first axis
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors>;
+
+  // Training pass, keeps whatever `backward` needs
+  fn forward(&mut self, inputs: &Tensor, rng: &mut dyn RngCore) -> Result<Tensor, NNErrors>;
+
+  // Takes the gradient w.r.t. the output of the last `forward`, stores the gradients
+  // of the layer's parameters and returns the gradient w.r.t. its inputs
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors>;
+
+  fn params(&self) -> Vec<ArrayViewD<'_, f64>> {
+    Vec::new()
+  }
+
+  fn params_mut(&mut self) -> Vec<Param<'_>> {
+    Vec::new()
+  }
+
+  // State that is not trained but saved with the layer, like running statistics
+  fn buffers(&self) -> Vec<ArrayViewD<'_, f64>> {
+    Vec::new()
+  }
+
+  fn buffers_mut(&mut self) -> Vec<ArrayViewMutD<'_, f64>> {
+    Vec::new()
+  }
+
+  // Redraws the weights with `init` and resets the biases, layers without weights ignore it
+  fn init(&mut self, _init: &Initializer, _rng: &mut dyn RngCore) {}
+
+  // Sets (or with `None` removes) the penalty on the weights, its gradient is added in `backward`
+  fn regularize(&mut self, _regularizer: Option<Regularizer>) -> Result<(), NNErrors> {
+    Err(NNErrors::LayerConfig(format!("{} has no weights to regularize", self.name())))
+  }
+
+  // Current value of that penalty
+  fn penalty(&self) -> f64 {
+    0.0
+  }
+
+  // Everything the builder needs to rebuild the layer, the parameters are saved next to it
+  fn config(&self) -> serde_json::Value;
+
+  fn box_clone(&self) -> Box<dyn Layer>;
+}
+
+impl Clone for Box<dyn Layer> {
+  fn clone(&self) -> Self {
+    self.box_clone()
+  }
+}
+
+pub type LayerBuilder = fn(&serde_json::Value) -> Result<Box<dyn Layer>, NNErrors>;
+
+fn registry() -> &'static RwLock<HashMap<String, LayerBuilder>> {
+  static REGISTRY: OnceLock<RwLock<HashMap<String, LayerBuilder>>> = OnceLock::new();
+
+  REGISTRY.get_or_init(|| {
+    let builtins: [(&str, LayerBuilder); 11] = [
+      (Dense::KIND, Dense::from_config),
+      (Conv2d::KIND, Conv2d::from_config),
+      (Dropout::KIND, Dropout::from_config),
+      (Activation::KIND, Activation::from_config),
+      (BatchNorm1d::KIND, BatchNorm1d::from_config),
+      (LayerNorm::KIND, LayerNorm::from_config),
+      (MaxPool2d::KIND, MaxPool2d::from_config),
+      (AvgPool2d::KIND, AvgPool2d::from_config),
+      (GlobalAveragePool::KIND, GlobalAveragePool::from_config),
+      (Flatten::KIND, Flatten::from_config),
+      (Reshape::KIND, Reshape::from_config),
+    ];
+
+    RwLock::new(builtins.into_iter().map(|(kind, builder)| (kind.to_string(), builder)).collect())
+  })
+}
+
+// Makes networks holding a custom layer loadable
+pub fn register_layer(kind: &str, builder: LayerBuilder) {
+  registry().write().unwrap().insert(kind.to_string(), builder);
+}
+
+// Rejects a gradient whose shape differs from the output of the last `forward`
+pub(crate) fn check_grad(expected: &[usize], grad: &Tensor) -> Result<(), NNErrors> {
+  if grad.shape() != expected {
+    return Err(NNErrors::ShapeMismatch(grad.shape().to_vec(), expected.to_vec()));
+  }
+  Ok(())
+}
+
+// Image layers take samples of [channels, height, width] or the same values flattened
+pub(crate) fn check_image(name: &str, (c, h, w): (usize, usize, usize), got: &[usize]) -> Result<(), NNErrors> {
+  if got != [c, h, w] && got != [c * h * w] {
+    return Err(NNErrors::InputShape(name.to_string(), vec![c, h, w], got.to_vec()));
+  }
+  Ok(())
+}
+
+pub(crate) fn to_images(name: &str, inputs: &Tensor, (c, h, w): (usize, usize, usize)) -> Result<Images, NNErrors> {
+  check_image(name, (c, h, w), tensor::sample_shape(inputs))?;
+  reshape_images(inputs, (c, h, w))
+}
+
+// A batch of maps of `(c, h, w)` as NCHW images, e.g. the gradient w.r.t. the output of an image layer
+pub(crate) fn reshape_images(tensor: &Tensor, (c, h, w): (usize, usize, usize)) -> Result<Images, NNErrors> {
+  let batch = tensor::batch_size(tensor);
+  tensor.to_shape((batch, c, h, w))
+    .map(|view| view.into_owned())
+    .map_err(|_| NNErrors::ShapeMismatch(tensor.shape().to_vec(), vec![batch, c, h, w]))
+}
+
+pub(crate) fn parse_config<'a, T: Deserialize<'a>>(config: &'a serde_json::Value) -> Result<T, NNErrors> {
+  T::deserialize(config).map_err(|e| NNErrors::LayerConfig(e.to_string()))
+}
+
+// Serialized form of a layer
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LayerRecord {
+  pub kind: String,
+  pub config: serde_json::Value,
+  pub params: Vec<ArrayD<f64>>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub buffers: Vec<ArrayD<f64>>,
+}
+
+impl LayerRecord {
+  pub fn from_layer(layer: &dyn Layer) -> LayerRecord {
+    LayerRecord {
+      kind: layer.kind().to_string(),
+      config: layer.config(),
+      params: layer.params().into_iter().map(|p| p.to_owned()).collect(),
+      buffers: layer.buffers().into_iter().map(|b| b.to_owned()).collect(),
+    }
+  }
+
+  pub fn into_layer(self) -> Result<Box<dyn Layer>, NNErrors> {
+    let builder = *registry()
+      .read()
+      .unwrap()
+      .get(&self.kind)
+      .ok_or_else(|| NNErrors::UnknownLayer(self.kind.clone()))?;
+
+    let mut layer = builder(&self.config)?;
+    let name = layer.name().to_string();
+
+    let mut params = layer.params_mut();
+    if params.len() != self.params.len() {
+      return Err(NNErrors::ParamMismatch(name));
+    }
+    for (param, value) in params.iter_mut().zip(self.params.iter()) {
+      if param.value.shape() != value.shape() {
+        return Err(NNErrors::ParamMismatch(name));
+      }
+      param.value.assign(value);
+    }
+    drop(params);
+
+    // Older records have no buffers, the layer then keeps its fresh ones
+    let mut buffers = layer.buffers_mut();
+    if !self.buffers.is_empty() && buffers.len() != self.buffers.len() {
+      return Err(NNErrors::ParamMismatch(name));
+    }
+    for (buffer, value) in buffers.iter_mut().zip(self.buffers.iter()) {
+      if buffer.shape() != value.shape() {
+        return Err(NNErrors::ParamMismatch(name));
+      }
+      buffer.assign(value);
+    }
+    drop(buffers);
+
+    Ok(layer)
+  }
+}
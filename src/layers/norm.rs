@@ -0,0 +1,367 @@
+use ndarray::{Array, ArrayViewD, ArrayViewMutD, Axis, Dim};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use crate::{errors::NNErrors, initializer::Initializer, tensor::{self, Tensor}};
+use super::{Layer, Param, check_grad, parse_config};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+type Vector = Array<f64, Dim<[usize; 1]>>;
+
+// Both layers normalize along the last axis of their samples, which has to hold `n_features`
+// values, e.g. [n_features] or [steps, n_features]
+fn check_features(name: &str, n_features: usize, input: &[usize]) -> Result<(), NNErrors> {
+  if input.last() != Some(&n_features) {
+    return Err(NNErrors::InputShape(name.to_string(), vec![n_features], input.to_vec()));
+  }
+  Ok(())
+}
+
+// Inputs as rows of `n_features` values
+fn feature_rows(name: &str, n_features: usize, inputs: &Tensor) -> Result<Matrix, NNErrors> {
+  check_features(name, n_features, tensor::sample_shape(inputs))?;
+  tensor::last_axis_rows(inputs)
+}
+
+// Normalizes every feature over the batch (and every other axis), then scales by `gamma` and shifts by `beta`.
+// Training uses the statistics of the batch and folds them into running ones with `momentum`,
+// inference (`output`) uses the running statistics.
+#[derive(Debug, Clone)]
+pub struct BatchNorm1d {
+  pub name: String,
+  pub n_features: usize,
+  // Weight of the newest batch in the running statistics
+  pub momentum: f64,
+  pub eps: f64,
+  pub gamma: Vector,
+  pub beta: Vector,
+  pub running_mean: Vector,
+  pub running_var: Vector,
+  grad_gamma: Vector,
+  grad_beta: Vector,
+  normalized: Matrix,
+  inv_std: Vector,
+  shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct BatchNormConfig {
+  name: String,
+  n_features: usize,
+  momentum: f64,
+  eps: f64,
+}
+
+impl BatchNorm1d {
+  pub const KIND: &'static str = "batch_norm_1d";
+
+  pub fn new(name: &str, n_features: usize) -> BatchNorm1d {
+    BatchNorm1d {
+      name: name.to_owned(),
+      n_features,
+      momentum: 0.1,
+      eps: 1e-5,
+      gamma: Array::ones(n_features),
+      beta: Array::zeros(n_features),
+      running_mean: Array::zeros(n_features),
+      running_var: Array::ones(n_features),
+      grad_gamma: Array::zeros(n_features),
+      grad_beta: Array::zeros(n_features),
+      normalized: Array::zeros((0, n_features)),
+      inv_std: Array::zeros(n_features),
+      shape: vec![0, n_features],
+    }
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: BatchNormConfig = parse_config(config)?;
+    let mut norm = BatchNorm1d::new(&config.name, config.n_features);
+    norm.momentum = config.momentum;
+    norm.eps = config.eps;
+    Ok(Box::new(norm))
+  }
+}
+
+impl Layer for BatchNorm1d {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    BatchNorm1d::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(vec![self.n_features])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    check_features(&self.name, self.n_features, input)?;
+    Ok(input.to_vec())
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    let rows = feature_rows(&self.name, self.n_features, inputs)?;
+    let inv_std = self.running_var.mapv(|v| 1.0 / (v + self.eps).sqrt());
+    tensor::reshape(&((rows - &self.running_mean) * &inv_std * &self.gamma + &self.beta), inputs.shape())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let inputs_shape = inputs.shape().to_vec();
+    let inputs = feature_rows(&self.name, self.n_features, inputs)?;
+    let n = inputs.nrows() as f64;
+    let mean = inputs.mean_axis(Axis(0)).unwrap_or_else(|| Array::zeros(self.n_features));
+    let var = inputs.var_axis(Axis(0), 0.0);
+
+    // The running variance estimates the population one, hence the unbiased batch variance
+    let unbiased = if n > 1.0 { &var * (n / (n - 1.0)) } else { var.clone() };
+    self.running_mean = &self.running_mean * (1.0 - self.momentum) + &mean * self.momentum;
+    self.running_var = &self.running_var * (1.0 - self.momentum) + unbiased * self.momentum;
+
+    self.inv_std = var.mapv(|v| 1.0 / (v + self.eps).sqrt());
+    self.normalized = (inputs - &mean) * &self.inv_std;
+    self.shape = inputs_shape;
+    tensor::reshape(&(&self.normalized * &self.gamma + &self.beta), &self.shape)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    check_grad(&self.shape, grad_out)?;
+    let grad_out = &tensor::last_axis_rows(grad_out)?;
+    self.grad_gamma = (grad_out * &self.normalized).sum_axis(Axis(0));
+    self.grad_beta = grad_out.sum_axis(Axis(0));
+
+    // Every output depends on the whole batch through its mean and variance
+    let n = grad_out.nrows() as f64;
+    let grad_norm = grad_out * &self.gamma;
+    let sum = grad_norm.sum_axis(Axis(0));
+    let dot = (&grad_norm * &self.normalized).sum_axis(Axis(0));
+    tensor::reshape(&((grad_norm * n - &sum - &self.normalized * &dot) * &self.inv_std / n), &self.shape)
+  }
+
+  fn params(&self) -> Vec<ArrayViewD<'_, f64>> {
+    vec![self.gamma.view().into_dyn(), self.beta.view().into_dyn()]
+  }
+
+  fn params_mut(&mut self) -> Vec<Param<'_>> {
+    vec![
+      Param { value: self.gamma.view_mut().into_dyn(), grad: self.grad_gamma.view_mut().into_dyn() },
+      Param { value: self.beta.view_mut().into_dyn(), grad: self.grad_beta.view_mut().into_dyn() },
+    ]
+  }
+
+  fn buffers(&self) -> Vec<ArrayViewD<'_, f64>> {
+    vec![self.running_mean.view().into_dyn(), self.running_var.view().into_dyn()]
+  }
+
+  fn buffers_mut(&mut self) -> Vec<ArrayViewMutD<'_, f64>> {
+    vec![self.running_mean.view_mut().into_dyn(), self.running_var.view_mut().into_dyn()]
+  }
+
+  // Back to the identity: unit scale, no shift, fresh running statistics
+  fn init(&mut self, _init: &Initializer, _rng: &mut dyn RngCore) {
+    self.gamma.fill(1.0);
+    self.beta.fill(0.0);
+    self.running_mean.fill(0.0);
+    self.running_var.fill(1.0);
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "n_features": self.n_features, "momentum": self.momentum, "eps": self.eps })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+// Normalizes every position over its features, then scales by `gamma` and shifts by `beta`.
+// Positions do not depend on each other, so training and inference behave the same.
+#[derive(Debug, Clone)]
+pub struct LayerNorm {
+  pub name: String,
+  pub n_features: usize,
+  pub eps: f64,
+  pub gamma: Vector,
+  pub beta: Vector,
+  grad_gamma: Vector,
+  grad_beta: Vector,
+  normalized: Matrix,
+  inv_std: Matrix,
+  shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct LayerNormConfig {
+  name: String,
+  n_features: usize,
+  eps: f64,
+}
+
+impl LayerNorm {
+  pub const KIND: &'static str = "layer_norm";
+
+  pub fn new(name: &str, n_features: usize) -> LayerNorm {
+    LayerNorm {
+      name: name.to_owned(),
+      n_features,
+      eps: 1e-5,
+      gamma: Array::ones(n_features),
+      beta: Array::zeros(n_features),
+      grad_gamma: Array::zeros(n_features),
+      grad_beta: Array::zeros(n_features),
+      normalized: Array::zeros((0, n_features)),
+      inv_std: Array::zeros((0, 1)),
+      shape: vec![0, n_features],
+    }
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: LayerNormConfig = parse_config(config)?;
+    let mut norm = LayerNorm::new(&config.name, config.n_features);
+    norm.eps = config.eps;
+    Ok(Box::new(norm))
+  }
+
+  // (normalized inputs, 1 / std of every row as a column)
+  fn normalize(&self, inputs: &Matrix) -> (Matrix, Matrix) {
+    let mean = inputs.mean_axis(Axis(1)).unwrap_or_else(|| Array::zeros(inputs.nrows())).insert_axis(Axis(1));
+    let inv_std = inputs.var_axis(Axis(1), 0.0).mapv(|v| 1.0 / (v + self.eps).sqrt()).insert_axis(Axis(1));
+    ((inputs - &mean) * &inv_std, inv_std)
+  }
+}
+
+impl Layer for LayerNorm {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    LayerNorm::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(vec![self.n_features])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    check_features(&self.name, self.n_features, input)?;
+    Ok(input.to_vec())
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    let rows = feature_rows(&self.name, self.n_features, inputs)?;
+    tensor::reshape(&(self.normalize(&rows).0 * &self.gamma + &self.beta), inputs.shape())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let rows = feature_rows(&self.name, self.n_features, inputs)?;
+    (self.normalized, self.inv_std) = self.normalize(&rows);
+    self.shape = inputs.shape().to_vec();
+    tensor::reshape(&(&self.normalized * &self.gamma + &self.beta), &self.shape)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    check_grad(&self.shape, grad_out)?;
+    let grad_out = &tensor::last_axis_rows(grad_out)?;
+    self.grad_gamma = (grad_out * &self.normalized).sum_axis(Axis(0));
+    self.grad_beta = grad_out.sum_axis(Axis(0));
+
+    let n = grad_out.ncols() as f64;
+    let grad_norm = grad_out * &self.gamma;
+    let sum = grad_norm.sum_axis(Axis(1)).insert_axis(Axis(1));
+    let dot = (&grad_norm * &self.normalized).sum_axis(Axis(1)).insert_axis(Axis(1));
+    tensor::reshape(&((grad_norm * n - &sum - &self.normalized * &dot) * &self.inv_std / n), &self.shape)
+  }
+
+  fn params(&self) -> Vec<ArrayViewD<'_, f64>> {
+    vec![self.gamma.view().into_dyn(), self.beta.view().into_dyn()]
+  }
+
+  fn params_mut(&mut self) -> Vec<Param<'_>> {
+    vec![
+      Param { value: self.gamma.view_mut().into_dyn(), grad: self.grad_gamma.view_mut().into_dyn() },
+      Param { value: self.beta.view_mut().into_dyn(), grad: self.grad_beta.view_mut().into_dyn() },
+    ]
+  }
+
+  fn init(&mut self, _init: &Initializer, _rng: &mut dyn RngCore) {
+    self.gamma.fill(1.0);
+    self.beta.fill(0.0);
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "n_features": self.n_features, "eps": self.eps })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ndarray::{Dimension, array, s};
+  use rand::{SeedableRng, rngs::StdRng};
+  use crate::network::{Format, Network};
+  use super::*;
+
+  fn close<D: Dimension>(a: &Array<f64, D>, b: &Array<f64, D>) {
+    assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12), "{a} != {b}");
+  }
+
+  // Batch means 3 and 30, unbiased variances 4 and 700
+  fn trained() -> BatchNorm1d {
+    let mut norm = BatchNorm1d::new("bn", 2);
+    norm.momentum = 0.2;
+    norm.gamma = array![2.0, 1.0];
+    norm.beta = array![0.5, 0.0];
+    norm.forward(&array![[1.0, 10.0], [3.0, 20.0], [5.0, 60.0]].into_dyn(), &mut StdRng::seed_from_u64(0)).unwrap();
+    norm
+  }
+
+  #[test]
+  fn running_stats_follow_momentum() {
+    let mut norm = trained();
+    close(&norm.running_mean, &array![0.6, 6.0]);
+    close(&norm.running_var, &array![1.6, 140.8]);
+
+    norm.forward(&array![[2.0, 0.0], [4.0, 0.0]].into_dyn(), &mut StdRng::seed_from_u64(0)).unwrap();
+    close(&norm.running_mean, &array![0.8 * 0.6 + 0.2 * 3.0, 0.8 * 6.0]);
+    close(&norm.running_var, &array![0.8 * 1.6 + 0.2 * 2.0, 0.8 * 140.8]);
+  }
+
+  #[test]
+  fn eval_uses_running_stats() {
+    let mut norm = trained();
+    let input = array![[1.0, 10.0]].into_dyn();
+    let eps = norm.eps;
+    let expected = array![[2.0 * 0.4 / (1.6 + eps).sqrt() + 0.5, 4.0 / (140.8 + eps).sqrt()]].into_dyn();
+    close(&norm.output(&input).unwrap(), &expected);
+
+    // Training mode normalizes with the statistics of the batch itself
+    let batch = array![[1.0, 10.0], [3.0, 20.0], [5.0, 60.0]].into_dyn();
+    let first = norm.forward(&batch, &mut StdRng::seed_from_u64(0)).unwrap().slice_move(s![0..1, ..]);
+    let batch_stats = array![[2.0 * -2.0 / (8.0 / 3.0 + eps).sqrt() + 0.5, -20.0 / (1400.0 / 3.0 + eps).sqrt()]].into_dyn();
+    close(&first.into_dyn(), &batch_stats);
+  }
+
+  #[test]
+  fn running_stats_survive_save_and_load() {
+    let mut nn = Network::empty();
+    nn.add_layer(trained());
+    let input = array![[1.0, 10.0], [-2.0, 50.0]];
+
+    for format in [Format::Json, Format::Binary] {
+      let path = std::env::temp_dir().join(format!("rust-nn-{}-batch-norm-{format:?}", std::process::id()));
+      let path = path.to_string_lossy();
+      nn.save(&path, format, None, None).unwrap();
+      let loaded = Network::load(&path, format, None, None);
+      std::fs::remove_file(&*path).unwrap();
+
+      let loaded = loaded.unwrap();
+      let (saved, restored) = (nn.layer("bn").unwrap(), loaded.layer("bn").unwrap());
+      assert_eq!(restored.buffers(), saved.buffers());
+      assert_eq!(loaded.output(&input).unwrap(), nn.output(&input).unwrap());
+    }
+  }
+}
@@ -0,0 +1,413 @@
+use ndarray::{s, Array, Axis, Dim};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use crate::{errors::NNErrors, tensor::{self, Tensor}};
+use super::{Layer, check_grad, check_image, parse_config, reshape_images, to_images};
+
+type Images = Array<f64, Dim<[usize; 4]>>;
+
+// Like `Conv2d`, pooling layers take samples of [channels, height, width] (or those values
+// flattened) and return samples of [channels, pooled height, pooled width].
+// Windows have to fit the image, positions past the last full window are dropped.
+#[derive(Deserialize)]
+struct Config {
+  name: String,
+  channels: usize,
+  input_size: (usize, usize),
+  window: usize,
+  step: usize,
+}
+
+fn check_window(name: &str, (h, w): (usize, usize), window: usize, step: usize) -> Result<(), NNErrors> {
+  if step == 0 {
+    return Err(NNErrors::LayerConfig(format!("{name} needs a step of at least 1")));
+  }
+  if window == 0 || window > h.min(w) {
+    return Err(NNErrors::LayerConfig(format!("{name} can not slide a window of {window} over {h}x{w} images")));
+  }
+  Ok(())
+}
+
+fn pooled_size((h, w): (usize, usize), window: usize, step: usize) -> (usize, usize) {
+  ((h - window) / step + 1, (w - window) / step + 1)
+}
+
+fn pooled_shape(name: &str, channels: usize, input_size: (usize, usize), window: usize, step: usize, input: &[usize])
+  -> Result<Vec<usize>, NNErrors> {
+  check_image(name, (channels, input_size.0, input_size.1), input)?;
+  let (oh, ow) = pooled_size(input_size, window, step);
+  Ok(vec![channels, oh, ow])
+}
+
+// Keeps the largest value of every window
+#[derive(Debug, Clone)]
+pub struct MaxPool2d {
+  pub name: String,
+  pub channels: usize,
+  pub input_size: (usize, usize),
+  pub window: usize,
+  pub step: usize,
+  // Flat input position of the maximum behind every output value of the last `forward`
+  argmax: Vec<usize>,
+  // Shape of the batch of the last `forward`, its gradient is returned in that shape
+  in_shape: Vec<usize>,
+}
+
+impl MaxPool2d {
+  pub const KIND: &'static str = "max_pool_2d";
+
+  pub fn new(
+    name: &str,
+    channels: usize,
+    input_size: (usize, usize),
+    window: usize,
+    step: usize,
+  ) -> Result<MaxPool2d, NNErrors> {
+    check_window(name, input_size, window, step)?;
+    Ok(MaxPool2d { name: name.to_owned(), channels, input_size, window, step, argmax: Vec::new(), in_shape: Vec::new() })
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let c: Config = parse_config(config)?;
+    Ok(Box::new(MaxPool2d::new(&c.name, c.channels, c.input_size, c.window, c.step)?))
+  }
+
+  pub fn output_size(&self) -> (usize, usize) {
+    pooled_size(self.input_size, self.window, self.step)
+  }
+
+  pub fn n_input(&self) -> usize {
+    self.channels * self.input_size.0 * self.input_size.1
+  }
+
+  pub fn n_output(&self) -> usize {
+    let (oh, ow) = self.output_size();
+    self.channels * oh * ow
+  }
+
+  fn image_shape(&self) -> (usize, usize, usize) {
+    (self.channels, self.input_size.0, self.input_size.1)
+  }
+
+  // Pooled maps and the flat input position every value was taken from
+  fn pool(&self, x: &Images) -> (Images, Vec<usize>) {
+    let (batch, (h, w), (oh, ow), k) = (x.shape()[0], self.input_size, self.output_size(), self.window);
+    let mut out = Array::zeros((batch, self.channels, oh, ow));
+    let mut argmax = Vec::with_capacity(out.len());
+
+    for n in 0..batch {
+      for c in 0..self.channels {
+        for i in 0..oh {
+          for j in 0..ow {
+            let (y0, x0) = (i * self.step, j * self.step);
+            let flat = |y: usize, x: usize| ((n * self.channels + c) * h + y) * w + x;
+            // Starting from the first position keeps the gradient inside the window even when
+            // it only holds -inf or NaN
+            let mut best = (x[[n, c, y0, x0]], flat(y0, x0));
+            for (dy, row) in x.slice(s![n, c, y0..y0 + k, x0..x0 + k]).rows().into_iter().enumerate() {
+              for (dx, v) in row.iter().enumerate() {
+                if *v > best.0 {
+                  best = (*v, flat(y0 + dy, x0 + dx));
+                }
+              }
+            }
+            out[[n, c, i, j]] = best.0;
+            argmax.push(best.1);
+          }
+        }
+      }
+    }
+
+    (out, argmax)
+  }
+}
+
+impl Layer for MaxPool2d {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    MaxPool2d::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(vec![self.channels, self.input_size.0, self.input_size.1])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    pooled_shape(&self.name, self.channels, self.input_size, self.window, self.step, input)
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    Ok(self.pool(&to_images(&self.name, inputs, self.image_shape())?).0.into_dyn())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let (out, argmax) = self.pool(&to_images(&self.name, inputs, self.image_shape())?);
+    self.argmax = argmax;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(out.into_dyn())
+  }
+
+  // Only the maximum of every window gets gradient
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    let (oh, ow) = self.output_size();
+    check_grad(&[self.in_shape.first().copied().unwrap_or(0), self.channels, oh, ow], grad_out)?;
+
+    let mut grad = vec![0.0; self.in_shape.iter().product()];
+    for (g, idx) in grad_out.iter().zip(self.argmax.iter()) {
+      grad[*idx] += g;
+    }
+    tensor::reshape(&Array::from(grad), &self.in_shape)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({
+      "name": self.name,
+      "channels": self.channels,
+      "input_size": self.input_size,
+      "window": self.window,
+      "step": self.step,
+    })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+// Averages every window
+#[derive(Debug, Clone)]
+pub struct AvgPool2d {
+  pub name: String,
+  pub channels: usize,
+  pub input_size: (usize, usize),
+  pub window: usize,
+  pub step: usize,
+  in_shape: Vec<usize>,
+}
+
+impl AvgPool2d {
+  pub const KIND: &'static str = "avg_pool_2d";
+
+  pub fn new(
+    name: &str,
+    channels: usize,
+    input_size: (usize, usize),
+    window: usize,
+    step: usize,
+  ) -> Result<AvgPool2d, NNErrors> {
+    check_window(name, input_size, window, step)?;
+    Ok(AvgPool2d { name: name.to_owned(), channels, input_size, window, step, in_shape: Vec::new() })
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let c: Config = parse_config(config)?;
+    Ok(Box::new(AvgPool2d::new(&c.name, c.channels, c.input_size, c.window, c.step)?))
+  }
+
+  pub fn output_size(&self) -> (usize, usize) {
+    pooled_size(self.input_size, self.window, self.step)
+  }
+
+  pub fn n_input(&self) -> usize {
+    self.channels * self.input_size.0 * self.input_size.1
+  }
+
+  pub fn n_output(&self) -> usize {
+    let (oh, ow) = self.output_size();
+    self.channels * oh * ow
+  }
+
+  fn image_shape(&self) -> (usize, usize, usize) {
+    (self.channels, self.input_size.0, self.input_size.1)
+  }
+
+  fn pool(&self, x: &Images) -> Images {
+    let ((oh, ow), k) = (self.output_size(), self.window);
+
+    let mut out = Array::zeros((x.shape()[0], self.channels, oh, ow));
+    for i in 0..oh {
+      for j in 0..ow {
+        let (y0, x0) = (i * self.step, j * self.step);
+        let window = x.slice(s![.., .., y0..y0 + k, x0..x0 + k]);
+        out.slice_mut(s![.., .., i, j]).assign(&(window.sum_axis(Axis(3)).sum_axis(Axis(2)) / (k * k) as f64));
+      }
+    }
+    out
+  }
+}
+
+impl Layer for AvgPool2d {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    AvgPool2d::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(vec![self.channels, self.input_size.0, self.input_size.1])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    pooled_shape(&self.name, self.channels, self.input_size, self.window, self.step, input)
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    Ok(self.pool(&to_images(&self.name, inputs, self.image_shape())?).into_dyn())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let out = self.output(inputs)?;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(out)
+  }
+
+  // Every input of a window gets an equal share of its gradient
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    let batch = self.in_shape.first().copied().unwrap_or(0);
+    let ((h, w), (oh, ow), k) = (self.input_size, self.output_size(), self.window);
+    check_grad(&[batch, self.channels, oh, ow], grad_out)?;
+    let grad_maps = reshape_images(grad_out, (self.channels, oh, ow))? / (k * k) as f64;
+
+    let mut grad = Array::zeros((batch, self.channels, h, w));
+    for i in 0..oh {
+      for j in 0..ow {
+        let (y0, x0) = (i * self.step, j * self.step);
+        let share = grad_maps.slice(s![.., .., i, j]).insert_axis(Axis(2)).insert_axis(Axis(3)).to_owned();
+        let mut window = grad.slice_mut(s![.., .., y0..y0 + k, x0..x0 + k]);
+        window += &share;
+      }
+    }
+    tensor::reshape(&grad, &self.in_shape)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({
+      "name": self.name,
+      "channels": self.channels,
+      "input_size": self.input_size,
+      "window": self.window,
+      "step": self.step,
+    })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+// Mean of every channel over the whole image, giving one feature per channel
+#[derive(Debug, Clone)]
+pub struct GlobalAveragePool {
+  pub name: String,
+  pub channels: usize,
+  pub input_size: (usize, usize),
+  in_shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct GlobalConfig {
+  name: String,
+  channels: usize,
+  input_size: (usize, usize),
+}
+
+impl GlobalAveragePool {
+  pub const KIND: &'static str = "global_average_pool";
+
+  pub fn new(name: &str, channels: usize, input_size: (usize, usize)) -> GlobalAveragePool {
+    GlobalAveragePool { name: name.to_owned(), channels, input_size, in_shape: Vec::new() }
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let c: GlobalConfig = parse_config(config)?;
+    Ok(Box::new(GlobalAveragePool::new(&c.name, c.channels, c.input_size)))
+  }
+
+  fn area(&self) -> usize {
+    self.input_size.0 * self.input_size.1
+  }
+}
+
+impl Layer for GlobalAveragePool {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    GlobalAveragePool::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(vec![self.channels, self.input_size.0, self.input_size.1])
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    check_image(&self.name, (self.channels, self.input_size.0, self.input_size.1), input)?;
+    Ok(vec![self.channels])
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    let maps = to_images(&self.name, inputs, (self.channels, self.input_size.0, self.input_size.1))?;
+    Ok((maps.sum_axis(Axis(3)).sum_axis(Axis(2)) / self.area() as f64).into_dyn())
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let out = self.output(inputs)?;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(out)
+  }
+
+  // Every position of a map gets an equal share of the gradient of its mean
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    let batch = self.in_shape.first().copied().unwrap_or(0);
+    check_grad(&[batch, self.channels], grad_out)?;
+    let share = tensor::reshape(grad_out, &[batch, self.channels, 1])? / self.area() as f64;
+    let grad = share.broadcast(vec![batch, self.channels, self.area()]).unwrap().to_owned();
+    tensor::reshape(&grad, &self.in_shape)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "channels": self.channels, "input_size": self.input_size })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, rngs::StdRng};
+  use super::*;
+
+  #[test]
+  fn rejects_windows_that_do_not_fit() {
+    assert!(matches!(MaxPool2d::new("p", 1, (2, 2), 3, 1), Err(NNErrors::LayerConfig(_))));
+    assert!(matches!(MaxPool2d::new("p", 1, (2, 2), 2, 0), Err(NNErrors::LayerConfig(_))));
+    assert!(matches!(AvgPool2d::new("p", 1, (2, 2), 0, 1), Err(NNErrors::LayerConfig(_))));
+    assert!(matches!(AvgPool2d::new("p", 1, (4, 2), 3, 1), Err(NNErrors::LayerConfig(_))));
+
+    let config = json!({ "name": "p", "channels": 1, "input_size": [2, 2], "window": 3, "step": 1 });
+    assert!(MaxPool2d::from_config(&config).is_err());
+    assert!(AvgPool2d::from_config(&config).is_err());
+  }
+
+  #[test]
+  fn max_pool_gradient_stays_in_its_window() {
+    let mut pool = MaxPool2d::new("p", 1, (2, 4), 2, 2).unwrap();
+    let mut inputs = Tensor::zeros(vec![1, 1, 2, 4]);
+    inputs.slice_mut(s![0, 0, .., 2..]).fill(f64::NEG_INFINITY);
+
+    pool.forward(&inputs, &mut StdRng::seed_from_u64(0)).unwrap();
+    let grad = pool.backward(&Tensor::ones(vec![1, 1, 1, 2])).unwrap();
+    assert_eq!(grad[[0, 0, 0, 0]], 1.0);
+    assert_eq!(grad[[0, 0, 0, 2]], 1.0);
+    assert_eq!(grad.sum(), 2.0);
+  }
+}
@@ -0,0 +1,179 @@
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use crate::{errors::NNErrors, tensor::{self, Tensor}};
+use super::{Layer, check_grad, parse_config};
+
+// Both layers read the values of every sample in row-major order with another shape.
+// They take samples of `input_shape` or any other shape holding as many values,
+// and hand the gradient back in the shape of their last input.
+
+fn check_size(name: &str, input_shape: &[usize], input: &[usize]) -> Result<(), NNErrors> {
+  if input.iter().product::<usize>() != input_shape.iter().product::<usize>() {
+    return Err(NNErrors::InputShape(name.to_string(), input_shape.to_vec(), input.to_vec()));
+  }
+  Ok(())
+}
+
+// Turns samples of `input_shape` (e.g. [channels, height, width]) into flat feature vectors
+#[derive(Debug, Clone)]
+pub struct Flatten {
+  pub name: String,
+  pub input_shape: Vec<usize>,
+  in_shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct FlattenConfig {
+  name: String,
+  input_shape: Vec<usize>,
+}
+
+impl Flatten {
+  pub const KIND: &'static str = "flatten";
+
+  pub fn new(name: &str, input_shape: &[usize]) -> Flatten {
+    Flatten { name: name.to_owned(), input_shape: input_shape.to_vec(), in_shape: Vec::new() }
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: FlattenConfig = parse_config(config)?;
+    Ok(Box::new(Flatten::new(&config.name, &config.input_shape)))
+  }
+
+  pub fn n_output(&self) -> usize {
+    self.input_shape.iter().product()
+  }
+}
+
+impl Layer for Flatten {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    Flatten::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(self.input_shape.clone())
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    check_size(&self.name, &self.input_shape, input)?;
+    Ok(vec![self.n_output()])
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    let shape = self.output_shape(tensor::sample_shape(inputs))?;
+    tensor::reshape(inputs, &tensor::batch_shape(tensor::batch_size(inputs), &shape))
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let out = self.output(inputs)?;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(out)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    check_grad(&[self.in_shape.first().copied().unwrap_or(0), self.n_output()], grad_out)?;
+    tensor::reshape(grad_out, &self.in_shape)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "input_shape": self.input_shape })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
+
+// Reads samples of `input_shape` as samples of `output_shape`, both holding as many values
+#[derive(Debug, Clone)]
+pub struct Reshape {
+  pub name: String,
+  pub input_shape: Vec<usize>,
+  pub output_shape: Vec<usize>,
+  in_shape: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct ReshapeConfig {
+  name: String,
+  input_shape: Vec<usize>,
+  output_shape: Vec<usize>,
+}
+
+impl Reshape {
+  pub const KIND: &'static str = "reshape";
+
+  pub fn new(name: &str, input_shape: &[usize], output_shape: &[usize]) -> Result<Reshape, NNErrors> {
+    let (n_in, n_out) = (input_shape.iter().product::<usize>(), output_shape.iter().product::<usize>());
+    if n_in != n_out {
+      return Err(NNErrors::LayerConfig(format!(
+        "{name} can not reshape {input_shape:?} ({n_in} values) into {output_shape:?} ({n_out} values)"
+      )));
+    }
+
+    Ok(Reshape {
+      name: name.to_owned(),
+      input_shape: input_shape.to_vec(),
+      output_shape: output_shape.to_vec(),
+      in_shape: Vec::new(),
+    })
+  }
+
+  pub fn from_config(config: &serde_json::Value) -> Result<Box<dyn Layer>, NNErrors> {
+    let config: ReshapeConfig = parse_config(config)?;
+    Ok(Box::new(Reshape::new(&config.name, &config.input_shape, &config.output_shape)?))
+  }
+
+  pub fn n_output(&self) -> usize {
+    self.output_shape.iter().product()
+  }
+}
+
+impl Layer for Reshape {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn kind(&self) -> &'static str {
+    Reshape::KIND
+  }
+
+  fn input_shape(&self) -> Option<Vec<usize>> {
+    Some(self.input_shape.clone())
+  }
+
+  fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    check_size(&self.name, &self.input_shape, input)?;
+    Ok(self.output_shape.clone())
+  }
+
+  fn output(&self, inputs: &Tensor) -> Result<Tensor, NNErrors> {
+    let shape = self.output_shape(tensor::sample_shape(inputs))?;
+    tensor::reshape(inputs, &tensor::batch_shape(tensor::batch_size(inputs), &shape))
+  }
+
+  fn forward(&mut self, inputs: &Tensor, _rng: &mut dyn RngCore) -> Result<Tensor, NNErrors> {
+    let out = self.output(inputs)?;
+    self.in_shape = inputs.shape().to_vec();
+    Ok(out)
+  }
+
+  fn backward(&mut self, grad_out: &Tensor) -> Result<Tensor, NNErrors> {
+    let batch = self.in_shape.first().copied().unwrap_or(0);
+    check_grad(&tensor::batch_shape(batch, &self.output_shape), grad_out)?;
+    tensor::reshape(grad_out, &self.in_shape)
+  }
+
+  fn config(&self) -> serde_json::Value {
+    json!({ "name": self.name, "input_shape": self.input_shape, "output_shape": self.output_shape })
+  }
+
+  fn box_clone(&self) -> Box<dyn Layer> {
+    Box::new(self.clone())
+  }
+}
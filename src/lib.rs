@@ -0,0 +1,44 @@
+pub mod preludes;
+pub mod loss;
+pub mod activation;
+pub mod initializer;
+pub mod errors;
+pub mod network;
+pub mod layers;
+pub mod optimizer;
+pub mod scheduler;
+pub mod trainer;
+pub mod weights;
+pub mod loader;
+pub mod metrics;
+pub mod data_processing;
+pub mod preprocessing;
+pub mod regularization;
+pub mod split;
+pub mod tensor;
+pub mod genetic;
+#[cfg(feature = "env")]
+pub mod enviroment;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+
+pub use activation::ActivationType;
+pub use data_processing::Series;
+pub use errors::NNErrors;
+pub use initializer::Initializer;
+pub use layers::{
+  Activation, AvgPool2d, BatchNorm1d, Conv2d, Dense, Dropout, Flatten, GlobalAveragePool, Layer, LayerNorm, MaxPool2d,
+  Reshape,
+};
+pub use loss::{Loss, Reduction};
+pub use network::{Format, Network, Out};
+pub use optimizer::{Adagrad, Adam, AdamW, Optimizer, RmsProp, Sgd};
+pub use preprocessing::{
+  ImputeStrategy, Imputer, LabelEncoder, MinMaxScaler, OneHotEncoder, OrdinalEncoder, Pipeline, RobustScaler,
+  StandardScaler, Transformer,
+};
+pub use regularization::{GradClip, Regularizer};
+pub use scheduler::{CosineAnnealing, ExponentialDecay, LinearWarmup, LrScheduler, OneCycle, ReduceOnPlateau, StepDecay};
+pub use split::{Fold, KFold, Split};
+pub use tensor::Tensor;
+pub use trainer::{Callback, Checkpoint, EarlyStopping, Logger, Trainer};
@@ -1,94 +1,724 @@
-use std::time::Instant;
-
-use ndarray::{Array, Dim};
-use ndarray::parallel::prelude::*;
-use crate::{weights::Weight, activation::ActivationType, network::Network};
-
-pub fn diff_loss(
-  f: &dyn Fn(
-    &dyn Fn(&Vec<Weight>, &Vec<f64>, ActivationType) -> f64,
-    ActivationType,
-    &Vec<Weight>,
-    &Vec<Vec<f64>>
-  ) -> f64,
-  nn: &dyn Fn(&Vec<Weight>, &Vec<f64>, ActivationType) -> f64,
-  activation: ActivationType,
-  vars: &Vec<Weight>,
-  x_trues: &Vec<Vec<f64>>,
-) -> f64 {
-  let h = 0.01;
-  return (
-    (f(nn, activation, vars, x_trues) + h) - f(nn, activation, vars, x_trues)
-  ) / h
-}
-
-pub fn partial_diff_loss(
-  loss: &dyn Fn(
-    &Network,
-    &Array<f64, Dim<[usize; 2]>>,
-    &Array<f64, Dim<[usize; 2]>>,
-  ) -> f64,
-  l_name: &String,
-  w_name: &String,
-  nn: &Network,
-  data_inp: &Array<f64, Dim<[usize; 2]>>,
-  x_trues: &Array<f64, Dim<[usize; 2]>>,
-  eps: f64,
-) -> f64 {
-  let mut new_nn = nn.to_owned();
-
-  let start = Instant::now();
-  new_nn.change_wi(l_name, w_name, eps);
-  let duration = start.elapsed();
-  println!("    Change weight in part. diff: {:?}", duration);
-
-  let start = Instant::now();
-  let fh = loss(&new_nn, data_inp, x_trues);
-  let duration = start.elapsed();
-  println!("    Calc fh in part. diff: {:?}", duration);
-  let fo = loss(nn, data_inp, x_trues);
-  
-  (fh - fo) / eps
-}
-
-pub fn cross_entropy_loss(
-  nn: &Network,
-  data_inp: &Array<f64, Dim<[usize; 2]>>,
-  x_trues: &Array<f64, Dim<[usize; 2]>>,
-) -> f64 {
-  let nn_out = nn.output(&data_inp);
-
-  let mut log_out = nn_out.clone();
-  log_out.par_mapv_inplace(|v| v.log2());
-
-  let mut div_log = 1.0 - nn_out.clone();
-  div_log.par_mapv_inplace(|v| v.log2());
-
-  let out = x_trues * log_out + (1.0 - x_trues) * div_log.clone();
-
-  -out.mean().unwrap()
-}
-
-pub fn binary_cross_entropy_loss(
-  nn: &Network,
-  data_inp: &Array<f64, Dim<[usize; 2]>>,
-  x_trues: &Array<f64, Dim<[usize; 2]>>,
-) -> f64 {
-  let nn_out = nn.output(&data_inp);
-
-  let out = (x_trues * nn_out.clone().mapv_into(|v| v.log2())) + ((1.0 - x_trues) * (1.0 - nn_out.mapv_into(|v| v.log2())));
-
-  out.mean().unwrap()
-}
-
-pub fn loss_mse(
-  nn: &Network,
-  data_inp: &Array<f64, Dim<[usize; 2]>>,
-  x_trues: &Array<f64, Dim<[usize; 2]>>,
-) -> f64 {
-  let nn_out = nn.output(&data_inp);
-  let mut dif = nn_out - x_trues;
-  dif.par_mapv_inplace(|v| v.powf(2.0));
-
-  dif.mean().unwrap()
-}
\ No newline at end of file
+use std::fmt;
+use ndarray::{Array, Axis, Dim, Zip};
+use crate::{activation::ActivationType, errors::NNErrors};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+type Vector = Array<f64, Dim<[usize; 1]>>;
+
+// Probabilities are clamped this far away from 0 and 1 before taking logs
+pub(crate) const EPS: f64 = 1e-12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduction {
+  // Weighted mean over the rows
+  #[default]
+  Mean,
+  Sum,
+  // One loss per row
+  None,
+}
+
+// What every loss can be configured with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LossWeights {
+  pub reduction: Reduction,
+  // Weight of the rows of every class, for one-hot targets or a single 0 / 1 target column
+  pub class_weights: Option<Vec<f64>>,
+}
+
+// A loss compares every row of the network output with the same row of the targets. Implementors
+// give the loss of every row and its gradient, weighting and reduction are shared.
+pub trait Loss: fmt::Debug {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector;
+
+  // Gradient of every row's loss w.r.t. that row of `output`
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix;
+
+  fn weights(&self) -> &LossWeights;
+
+  fn weights_mut(&mut self) -> &mut LossWeights;
+
+  // Rejects targets the loss can not compare `output` with, by default anything but the output's shape
+  fn check_targets(&self, output: &Matrix, targets: &Matrix) -> Result<(), NNErrors> {
+    if output.dim() != targets.dim() {
+      return Err(NNErrors::ShapeMismatch(output.shape().to_vec(), targets.shape().to_vec()));
+    }
+    Ok(())
+  }
+
+  fn with_reduction(mut self, reduction: Reduction) -> Self where Self: Sized {
+    self.weights_mut().reduction = reduction;
+    self
+  }
+
+  fn with_class_weights(mut self, class_weights: Vec<f64>) -> Self where Self: Sized {
+    self.weights_mut().class_weights = Some(class_weights);
+    self
+  }
+
+  // Sample weight of every row (1 when not given) times the class weight of its target
+  fn row_weights(&self, targets: &Matrix, sample_weights: Option<&Vector>) -> Vector {
+    let mut weights = sample_weights.cloned().unwrap_or_else(|| Array::ones(targets.nrows()));
+    if let Some(class_weights) = &self.weights().class_weights {
+      weights *= &class_weight(targets, class_weights);
+    }
+    weights
+  }
+
+  // A single value for `Mean` and `Sum`, one per row for `None`
+  fn forward(&self, output: &Matrix, targets: &Matrix, sample_weights: Option<&Vector>) -> Vector {
+    let weights = self.row_weights(targets, sample_weights);
+    let losses = self.per_sample(output, targets) * &weights;
+
+    match self.weights().reduction {
+      Reduction::None => losses,
+      Reduction::Sum => Array::from_elem(1, losses.sum()),
+      Reduction::Mean => Array::from_elem(1, losses.sum() / total(&weights)),
+    }
+  }
+
+  // The reduced loss, summed over the rows for `None`
+  fn value(&self, output: &Matrix, targets: &Matrix, sample_weights: Option<&Vector>) -> f64 {
+    self.forward(output, targets, sample_weights).sum()
+  }
+
+  // Gradient of `value` w.r.t. `output`
+  fn backward(&self, output: &Matrix, targets: &Matrix, sample_weights: Option<&Vector>) -> Matrix {
+    let weights = self.row_weights(targets, sample_weights);
+    let grad = self.per_sample_grad(output, targets) * weights.view().insert_axis(Axis(1));
+
+    match self.weights().reduction {
+      Reduction::Mean => grad / total(&weights),
+      _ => grad,
+    }
+  }
+}
+
+// Mean squared error over the columns of a row
+#[derive(Debug, Clone, Default)]
+pub struct Mse {
+  pub weights: LossWeights,
+}
+
+impl Mse {
+  pub fn new() -> Mse {
+    Mse::default()
+  }
+}
+
+impl Loss for Mse {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    row_mean((output - targets).mapv(|e| e * e))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    2.0 * (output - targets) / output.ncols() as f64
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Mean absolute error over the columns of a row
+#[derive(Debug, Clone, Default)]
+pub struct Mae {
+  pub weights: LossWeights,
+}
+
+impl Mae {
+  pub fn new() -> Mae {
+    Mae::default()
+  }
+}
+
+impl Loss for Mae {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    row_mean((output - targets).mapv(f64::abs))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    (output - targets).mapv(sign) / output.ncols() as f64
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Squared error for errors up to `delta`, absolute error past it. Smooth L1 is the same curve
+// divided by `delta` (see `smooth_l1`).
+#[derive(Debug, Clone)]
+pub struct Huber {
+  pub delta: f64,
+  pub scale: f64,
+  pub weights: LossWeights,
+}
+
+impl Huber {
+  pub fn new(delta: f64) -> Huber {
+    Huber { delta, scale: 1.0, weights: LossWeights::default() }
+  }
+
+  pub fn smooth_l1(beta: f64) -> Huber {
+    Huber { delta: beta, scale: 1.0 / beta, weights: LossWeights::default() }
+  }
+}
+
+impl Loss for Huber {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    let delta = self.delta;
+    row_mean((output - targets).mapv(|e| {
+      if e.abs() <= delta { 0.5 * e * e } else { delta * (e.abs() - 0.5 * delta) }
+    })) * self.scale
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let delta = self.delta;
+    (output - targets).mapv(|e| e.clamp(-delta, delta)) * self.scale / output.ncols() as f64
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Cross-entropy of every output, read as the probability of its target being 1, averaged over
+// the columns of a row. Fits a sigmoid head, for one or several independent labels.
+#[derive(Debug, Clone, Default)]
+pub struct BinaryCrossEntropy {
+  pub weights: LossWeights,
+}
+
+impl BinaryCrossEntropy {
+  pub fn new() -> BinaryCrossEntropy {
+    BinaryCrossEntropy::default()
+  }
+}
+
+impl Loss for BinaryCrossEntropy {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    row_mean(Zip::from(output).and(targets).map_collect(|p, t| {
+      let p = p.clamp(EPS, 1.0 - EPS);
+      -(t * p.ln() + (1.0 - t) * (1.0 - p).ln())
+    }))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let n = output.ncols() as f64;
+    Zip::from(output).and(targets).map_collect(|p, t| {
+      let p = p.clamp(EPS, 1.0 - EPS);
+      (p - t) / (p * (1.0 - p)) / n
+    })
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Cross-entropy of softmax(scores) against the class distribution of a row. The network has
+// to output raw scores, i.e. not end with a Softmax activation.
+#[derive(Debug, Clone, Default)]
+pub struct SoftmaxCrossEntropy {
+  pub weights: LossWeights,
+}
+
+impl SoftmaxCrossEntropy {
+  pub fn new() -> SoftmaxCrossEntropy {
+    SoftmaxCrossEntropy::default()
+  }
+}
+
+impl Loss for SoftmaxCrossEntropy {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    -(targets * &ActivationType::log_softmax(output)).sum_axis(Axis(1))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let mass = targets.sum_axis(Axis(1)).insert_axis(Axis(1));
+    ActivationType::softmax(output) * &mass - targets
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Negative log-likelihood of the true class, the network output holding log-probabilities
+// (e.g. from `ActivationType::log_softmax`)
+#[derive(Debug, Clone, Default)]
+pub struct Nll {
+  pub weights: LossWeights,
+}
+
+impl Nll {
+  pub fn new() -> Nll {
+    Nll::default()
+  }
+}
+
+impl Loss for Nll {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    -(targets * output).sum_axis(Axis(1))
+  }
+
+  fn per_sample_grad(&self, _output: &Matrix, targets: &Matrix) -> Matrix {
+    -targets
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// KL(targets || output) per row, both holding probability distributions
+#[derive(Debug, Clone, Default)]
+pub struct KlDivergence {
+  pub weights: LossWeights,
+}
+
+impl KlDivergence {
+  pub fn new() -> KlDivergence {
+    KlDivergence::default()
+  }
+}
+
+impl Loss for KlDivergence {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    Zip::from(output).and(targets).map_collect(|p, t| {
+      if *t <= 0.0 { 0.0 } else { t * (t.ln() - p.max(EPS).ln()) }
+    }).sum_axis(Axis(1))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    Zip::from(output).and(targets).map_collect(|p, t| -t / p.max(EPS))
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// max(0, 1 - y * output) averaged over the columns of a row. Targets may be 0 / 1 or -1 / 1,
+// anything not above 0 is the negative class.
+#[derive(Debug, Clone, Default)]
+pub struct Hinge {
+  pub weights: LossWeights,
+}
+
+impl Hinge {
+  pub fn new() -> Hinge {
+    Hinge::default()
+  }
+}
+
+impl Loss for Hinge {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    row_mean(margins(output, targets))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let n = output.ncols() as f64;
+    Zip::from(output).and(targets).map_collect(|o, t| {
+      let y = label_sign(*t);
+      if 1.0 - y * o > 0.0 { -y / n } else { 0.0 }
+    })
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Square of the hinge loss, smooth at the margin
+#[derive(Debug, Clone, Default)]
+pub struct SquaredHinge {
+  pub weights: LossWeights,
+}
+
+impl SquaredHinge {
+  pub fn new() -> SquaredHinge {
+    SquaredHinge::default()
+  }
+}
+
+impl Loss for SquaredHinge {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    row_mean(margins(output, targets).mapv(|m| m * m))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let n = output.ncols() as f64;
+    Zip::from(output).and(targets).map_collect(|o, t| {
+      let y = label_sign(*t);
+      -2.0 * y * (1.0 - y * o).max(0.0) / n
+    })
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Cross-entropy scaled down by (1 - p)^gamma on the true classes, so easy examples weigh less.
+// The output holds probabilities: one column for a binary problem, or one per class.
+// The alpha balance of the paper is given with `with_class_weights`.
+#[derive(Debug, Clone)]
+pub struct Focal {
+  pub gamma: f64,
+  pub weights: LossWeights,
+}
+
+impl Focal {
+  pub fn new(gamma: f64) -> Focal {
+    Focal { gamma, weights: LossWeights::default() }
+  }
+
+  fn term(&self, t: f64, p: f64) -> f64 {
+    let p = p.clamp(EPS, 1.0 - EPS);
+    -t * (1.0 - p).powf(self.gamma) * p.ln()
+  }
+
+  fn term_grad(&self, t: f64, p: f64) -> f64 {
+    let p = p.clamp(EPS, 1.0 - EPS);
+    let gamma = self.gamma;
+    t * (gamma * (1.0 - p).powf(gamma - 1.0) * p.ln() - (1.0 - p).powf(gamma) / p)
+  }
+}
+
+impl Loss for Focal {
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    let binary = output.ncols() == 1;
+    Zip::from(output).and(targets).map_collect(|p, t| {
+      let loss = self.term(*t, *p);
+      if binary { loss + self.term(1.0 - t, 1.0 - p) } else { loss }
+    }).sum_axis(Axis(1))
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let binary = output.ncols() == 1;
+    Zip::from(output).and(targets).map_collect(|p, t| {
+      let grad = self.term_grad(*t, *p);
+      if binary { grad - self.term_grad(1.0 - t, 1.0 - p) } else { grad }
+    })
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+// Pulls every output row towards the direction of its target row, or pushes it away to a cosine
+// below `margin`. Targets have the output's columns plus an optional last column with 1 for
+// pairs that should match and -1 for pairs that should not; without it all pairs should match.
+#[derive(Debug, Clone)]
+pub struct CosineEmbedding {
+  pub margin: f64,
+  pub weights: LossWeights,
+}
+
+impl CosineEmbedding {
+  pub fn new(margin: f64) -> CosineEmbedding {
+    CosineEmbedding { margin, weights: LossWeights::default() }
+  }
+
+  // (cosine, gradient of the cosine w.r.t. the output row, should match) of every row
+  fn cosines(&self, output: &Matrix, targets: &Matrix) -> Vec<(f64, Vector, bool)> {
+    let d = output.ncols();
+    output.rows().into_iter().zip(targets.rows()).map(|(o, t)| {
+      let (t, similar) = (t.slice(ndarray::s![..d]), t.get(d).is_none_or(|y| *y > 0.0));
+      let (o_norm, t_norm) = (o.dot(&o).sqrt().max(EPS), t.dot(&t).sqrt().max(EPS));
+      let cos = o.dot(&t) / (o_norm * t_norm);
+      let grad = &t / (o_norm * t_norm) - &o * (cos / (o_norm * o_norm));
+      (cos, grad, similar)
+    }).collect()
+  }
+}
+
+impl Loss for CosineEmbedding {
+  // The targets may carry the pair labels as one extra column
+  fn check_targets(&self, output: &Matrix, targets: &Matrix) -> Result<(), NNErrors> {
+    let (rows, cols) = output.dim();
+    if targets.nrows() != rows || !(cols..=cols + 1).contains(&targets.ncols()) {
+      return Err(NNErrors::ShapeMismatch(output.shape().to_vec(), targets.shape().to_vec()));
+    }
+    Ok(())
+  }
+
+  fn per_sample(&self, output: &Matrix, targets: &Matrix) -> Vector {
+    self.cosines(output, targets).into_iter()
+      .map(|(cos, _, similar)| if similar { 1.0 - cos } else { (cos - self.margin).max(0.0) })
+      .collect()
+  }
+
+  fn per_sample_grad(&self, output: &Matrix, targets: &Matrix) -> Matrix {
+    let mut grad = Array::zeros(output.raw_dim());
+    for (mut row, (cos, cos_grad, similar)) in grad.rows_mut().into_iter().zip(self.cosines(output, targets)) {
+      if similar {
+        row.assign(&-cos_grad);
+      } else if cos > self.margin {
+        row.assign(&cos_grad);
+      }
+    }
+    grad
+  }
+
+  fn weights(&self) -> &LossWeights {
+    &self.weights
+  }
+
+  fn weights_mut(&mut self) -> &mut LossWeights {
+    &mut self.weights
+  }
+}
+
+fn row_mean(values: Matrix) -> Vector {
+  let n = values.ncols().max(1) as f64;
+  values.sum_axis(Axis(1)) / n
+}
+
+// A zero total weight gives a zero loss instead of NaN
+fn total(weights: &Vector) -> f64 {
+  let sum = weights.sum();
+  if sum == 0.0 { 1.0 } else { sum }
+}
+
+fn class_weight(targets: &Matrix, class_weights: &[f64]) -> Vector {
+  let weight = |c: usize| class_weights.get(c).copied().unwrap_or(1.0);
+  targets.rows().into_iter().map(|row| {
+    if row.len() == 1 {
+      row[0] * weight(1) + (1.0 - row[0]) * weight(0)
+    } else {
+      row.iter().enumerate().map(|(c, t)| t * weight(c)).sum()
+    }
+  }).collect()
+}
+
+fn sign(x: f64) -> f64 {
+  if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 }
+}
+
+fn label_sign(t: f64) -> f64 {
+  if t > 0.0 { 1.0 } else { -1.0 }
+}
+
+fn margins(output: &Matrix, targets: &Matrix) -> Matrix {
+  Zip::from(output).and(targets).map_collect(|o, t| (1.0 - label_sign(*t) * o).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+  use ndarray::array;
+  use crate::{layers::Dense, network::Network, optimizer::Sgd};
+  use super::*;
+
+  const TOL: f64 = 1e-6;
+
+  fn close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+  }
+
+  // Compares `backward` with central differences of `value`
+  fn check_grad(loss: &dyn Loss, output: &Matrix, targets: &Matrix, sample_weights: Option<&Vector>) {
+    let grad = loss.backward(output, targets, sample_weights);
+    let eps = 1e-6;
+    for ((i, j), g) in grad.indexed_iter() {
+      let (mut up, mut down) = (output.clone(), output.clone());
+      up[[i, j]] += eps;
+      down[[i, j]] -= eps;
+      let numeric = (loss.value(&up, targets, sample_weights) - loss.value(&down, targets, sample_weights)) / (2.0 * eps);
+      assert!((numeric - g).abs() < TOL, "{loss:?} at ({i}, {j}): {numeric} vs {g}");
+    }
+  }
+
+  // Every reduction, with and without class and sample weights
+  fn check_all<L: Loss + Clone>(loss: L, output: &Matrix, targets: &Matrix, class_weights: Vec<f64>) {
+    let sample_weights = Array::from_shape_fn(output.nrows(), |i| 0.5 + i as f64);
+    for reduction in [Reduction::Mean, Reduction::Sum, Reduction::None] {
+      let plain = loss.clone().with_reduction(reduction);
+      check_grad(&plain, output, targets, None);
+      check_grad(&plain, output, targets, Some(&sample_weights));
+      let weighted = plain.with_class_weights(class_weights.clone());
+      check_grad(&weighted, output, targets, Some(&sample_weights));
+    }
+  }
+
+  fn scores() -> (Matrix, Matrix) {
+    (array![[0.3, -1.2, 0.8], [1.5, 0.1, -0.4], [-0.7, 0.9, 0.2]], array![[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]])
+  }
+
+  fn probabilities() -> (Matrix, Matrix) {
+    (array![[0.7, 0.2, 0.1], [0.25, 0.35, 0.4], [0.1, 0.6, 0.3]], array![[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]])
+  }
+
+  #[test]
+  fn regression_values() {
+    let (output, targets) = (array![[1.0, 2.0], [3.0, 4.0]], array![[0.0, 2.0], [3.0, 6.0]]);
+
+    close(Mse::new().value(&output, &targets, None), 1.25);
+    close(Mse::new().with_reduction(Reduction::Sum).value(&output, &targets, None), 2.5);
+    assert_eq!(Mse::new().with_reduction(Reduction::None).forward(&output, &targets, None), array![0.5, 2.0]);
+    close(Mae::new().value(&output, &targets, None), 0.75);
+    close(Huber::new(1.0).value(&output, &targets, None), (0.25 + 0.75) / 2.0);
+    close(Huber::smooth_l1(2.0).value(&output, &targets, None), (0.125 + 0.5) / 2.0);
+  }
+
+  #[test]
+  fn classification_values() {
+    close(BinaryCrossEntropy::new().value(&array![[0.8]], &array![[1.0]], None), -(0.8f64).ln());
+    close(SoftmaxCrossEntropy::new().value(&array![[0.0, 3f64.ln()]], &array![[0.0, 1.0]], None), -(0.75f64).ln());
+    close(Nll::new().value(&array![[0.25f64.ln(), 0.75f64.ln()]], &array![[0.0, 1.0]], None), -(0.75f64).ln());
+    close(
+      KlDivergence::new().value(&array![[0.25, 0.75]], &array![[0.5, 0.5]], None),
+      0.5 * 2f64.ln() + 0.5 * (0.5f64 / 0.75).ln(),
+    );
+    close(Hinge::new().value(&array![[0.5, -2.0]], &array![[1.0, 0.0]], None), 0.25);
+    close(SquaredHinge::new().value(&array![[0.5, -2.0]], &array![[1.0, 0.0]], None), 0.125);
+    // Without focusing the binary focal loss is the binary cross-entropy
+    close(Focal::new(0.0).value(&array![[0.8]], &array![[1.0]], None), -(0.8f64).ln());
+    close(Focal::new(2.0).value(&array![[0.2, 0.8]], &array![[0.0, 1.0]], None), -0.04 * 0.8f64.ln());
+  }
+
+  #[test]
+  fn cosine_embedding_values() {
+    let loss = CosineEmbedding::new(0.5);
+    let output = array![[1.0, 0.0]];
+    close(loss.value(&output, &array![[1.0, 1.0]], None), 1.0 - 0.5f64.sqrt());
+    close(loss.value(&output, &array![[1.0, 1.0, 1.0]], None), 1.0 - 0.5f64.sqrt());
+    close(loss.value(&output, &array![[1.0, 1.0, -1.0]], None), 0.5f64.sqrt() - 0.5);
+    close(loss.value(&output, &array![[0.0, 1.0, -1.0]], None), 0.0);
+  }
+
+  #[test]
+  fn weights_and_reductions() {
+    let (output, targets) = (array![[1.0, 0.0], [0.0, 0.0]], array![[0.0, 1.0], [1.0, 0.0]]);
+    // Row losses 1 and 0.5, class weights pick 3 for the first row and 2 for the second
+    let loss = Mse::new().with_class_weights(vec![2.0, 3.0]);
+    close(loss.value(&output, &targets, None), (3.0 + 1.0) / 5.0);
+
+    let sample_weights = array![1.0, 4.0];
+    close(loss.value(&output, &targets, Some(&sample_weights)), (3.0 + 4.0) / 11.0);
+    close(loss.clone().with_reduction(Reduction::Sum).value(&output, &targets, Some(&sample_weights)), 7.0);
+    assert_eq!(loss.with_reduction(Reduction::None).forward(&output, &targets, Some(&sample_weights)), array![3.0, 4.0]);
+
+    // With a single 0 / 1 column positives take the second class weight and negatives the first
+    let loss = BinaryCrossEntropy::new().with_class_weights(vec![1.0, 3.0]);
+    let (p, t) = (array![[0.8], [0.4]], array![[1.0], [0.0]]);
+    close(loss.value(&p, &t, None), (3.0 * -(0.8f64).ln() + -(0.6f64).ln()) / 4.0);
+  }
+
+  #[test]
+  fn gradients_match_finite_differences() {
+    let (s, t) = scores();
+    let (p, pt) = probabilities();
+    let class_weights = vec![0.5, 2.0, 1.5];
+
+    check_all(Mse::new(), &s, &t, class_weights.clone());
+    check_all(Mae::new(), &s, &t, class_weights.clone());
+    check_all(Huber::new(0.5), &s, &t, class_weights.clone());
+    check_all(Huber::smooth_l1(0.5), &s, &t, class_weights.clone());
+    check_all(BinaryCrossEntropy::new(), &p, &pt, class_weights.clone());
+    check_all(SoftmaxCrossEntropy::new(), &s, &t, class_weights.clone());
+    check_all(Nll::new(), &p.mapv(f64::ln), &pt, class_weights.clone());
+    check_all(KlDivergence::new(), &p, &array![[0.6, 0.3, 0.1], [0.2, 0.2, 0.6], [0.3, 0.5, 0.2]], class_weights.clone());
+    check_all(Hinge::new(), &s, &t, class_weights.clone());
+    check_all(SquaredHinge::new(), &s, &t, class_weights.clone());
+    check_all(Focal::new(2.0), &p, &pt, class_weights.clone());
+    check_all(Focal::new(1.5), &array![[0.3], [0.9], [0.6]], &array![[1.0], [0.0], [1.0]], vec![0.25, 0.75]);
+
+    let pairs = array![[1.0, 0.5, 0.0, 1.0], [0.2, 1.0, 0.3, -1.0], [-0.5, 1.0, 1.0, -1.0]];
+    check_all(CosineEmbedding::new(0.1), &s, &pairs, class_weights);
+  }
+
+  #[test]
+  fn losses_check_target_columns() {
+    let output = Array::zeros((2, 3));
+    assert!(Mse::new().check_targets(&output, &Array::zeros((2, 3))).is_ok());
+    assert!(matches!(Mse::new().check_targets(&output, &Array::zeros((2, 4))), Err(NNErrors::ShapeMismatch(..))));
+
+    let cosine = CosineEmbedding::new(0.0);
+    assert!(cosine.check_targets(&output, &Array::zeros((2, 3))).is_ok());
+    assert!(cosine.check_targets(&output, &Array::zeros((2, 4))).is_ok());
+    assert!(cosine.check_targets(&output, &Array::zeros((2, 5))).is_err());
+    assert!(cosine.check_targets(&output, &Array::zeros((3, 4))).is_err());
+  }
+
+  #[test]
+  fn network_trains_on_cosine_pair_labels() {
+    let mut nn = Network::empty();
+    let mut dense = Dense::new("d", 2, 2);
+    dense.weights = array![[1.0, 0.3], [0.2, 1.0]];
+    nn.add_layer(dense);
+
+    // The second pair should not match but starts out pointing the same way
+    let inputs = array![[1.0, 0.0], [0.0, 1.0]];
+    let targets = array![[1.0, 0.0, 1.0], [0.0, 1.0, -1.0]];
+    let loss = CosineEmbedding::new(0.0);
+    let before = loss.value(&nn.output(&inputs).unwrap(), &targets, None);
+
+    let mut sgd = Sgd::new(0.5);
+    for _ in 0..20 {
+      nn.train_layer(&loss, &inputs, &targets, None, None, &mut sgd).unwrap();
+    }
+    assert!(loss.value(&nn.output(&inputs).unwrap(), &targets, None) < before);
+
+    let mse = Mse::new();
+    assert!(matches!(nn.train_layer(&mse, &inputs, &targets, None, None, &mut sgd), Err(NNErrors::ShapeMismatch(..))));
+    assert!(matches!(
+      nn.train_layer(&mse, &inputs, &Array::zeros((3, 2)), None, None, &mut sgd),
+      Err(NNErrors::RowMismatch(2, 3)),
+    ));
+  }
+
+  #[test]
+  fn sample_weights_need_one_value_per_row() {
+    let mut nn = Network::empty();
+    nn.add_layer(Dense::new("d", 2, 2));
+    let (inputs, targets) = (array![[1.0, 0.0], [0.0, 1.0]], array![[1.0, 0.0], [0.0, 1.0]]);
+
+    let weights = array![1.0, 2.0, 3.0];
+    assert!(matches!(nn.backprop(&Mse::new(), &inputs, &targets, Some(&weights)), Err(NNErrors::RowMismatch(3, 2))));
+    assert!(nn.backprop(&Mse::new(), &inputs, &targets, Some(&array![1.0, 2.0])).is_ok());
+  }
+}
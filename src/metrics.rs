@@ -0,0 +1,433 @@
+use ndarray::{Array, Axis, Dim};
+use crate::{
+  errors::NNErrors,
+  loss::EPS,
+  preludes::argmax,
+};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+// Classification metrics take the `Network::output` array and the class of every row.
+// An output with a single column is read as the probability of class 1 (sigmoid head),
+// otherwise every column holds the score of one class.
+
+// How per-class scores are combined into one number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Average {
+  // Unweighted mean over the classes
+  Macro,
+  // Computed from the counts pooled over all classes
+  Micro,
+  // Mean over the classes weighted by how many rows belong to each
+  Weighted,
+}
+
+// Points of a ROC or precision-recall curve, one per distinct score, from the highest threshold down
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Curve {
+  pub x: Vec<f64>,
+  pub y: Vec<f64>,
+  pub thresholds: Vec<f64>,
+}
+
+// Predicted class of every row
+pub fn predictions(output: &Matrix) -> Vec<usize> {
+  if output.ncols() == 1 {
+    output.column(0).iter().map(|p| usize::from(*p >= 0.5)).collect()
+  } else {
+    output.rows().into_iter().map(|row| argmax(&row.to_vec()).0).collect()
+  }
+}
+
+pub fn accuracy(output: &Matrix, labels: &[usize]) -> Result<f64, NNErrors> {
+  check_rows(output, labels)?;
+  let correct = predictions(output).iter().zip(labels).filter(|(p, l)| p == l).count();
+  Ok(correct as f64 / labels.len() as f64)
+}
+
+// Share of rows whose class is among the `k` highest scores
+pub fn top_k_accuracy(output: &Matrix, labels: &[usize], k: usize) -> Result<f64, NNErrors> {
+  check_rows(output, labels)?;
+  let hits = labels.iter().enumerate().filter(|(row, label)| {
+    let score = class_score(output, *row, **label);
+    let higher = (0..n_classes(output)).filter(|c| class_score(output, *row, *c) > score).count();
+    higher < k
+  }).count();
+  Ok(hits as f64 / labels.len() as f64)
+}
+
+// Rows are true classes, columns predicted ones
+pub fn confusion_matrix(output: &Matrix, labels: &[usize]) -> Result<Array<usize, Dim<[usize; 2]>>, NNErrors> {
+  check_rows(output, labels)?;
+  let n = labels.iter().map(|l| l + 1).fold(n_classes(output), usize::max);
+  let mut matrix = Array::zeros((n, n));
+  for (pred, label) in predictions(output).into_iter().zip(labels) {
+    matrix[[*label, pred]] += 1;
+  }
+  Ok(matrix)
+}
+
+pub fn precision(output: &Matrix, labels: &[usize], average: Average) -> Result<f64, NNErrors> {
+  Ok(precision_recall_f1(output, labels, average)?.0)
+}
+
+pub fn recall(output: &Matrix, labels: &[usize], average: Average) -> Result<f64, NNErrors> {
+  Ok(precision_recall_f1(output, labels, average)?.1)
+}
+
+pub fn f1(output: &Matrix, labels: &[usize], average: Average) -> Result<f64, NNErrors> {
+  Ok(precision_recall_f1(output, labels, average)?.2)
+}
+
+// Classes never predicted (or never present) count as a precision (or recall) of 0
+pub fn precision_recall_f1(output: &Matrix, labels: &[usize], average: Average) -> Result<(f64, f64, f64), NNErrors> {
+  let confusion = confusion_matrix(output, labels)?;
+  let tp = confusion.diag().to_vec();
+  let predicted = confusion.sum_axis(Axis(0)).to_vec();
+  let support = confusion.sum_axis(Axis(1)).to_vec();
+
+  if average == Average::Micro {
+    let p = ratio(tp.iter().sum(), predicted.iter().sum());
+    let r = ratio(tp.iter().sum(), support.iter().sum());
+    return Ok((p, r, harmonic(p, r)));
+  }
+
+  let weights: Vec<f64> = match average {
+    Average::Weighted => support.iter().map(|s| *s as f64 / labels.len() as f64).collect(),
+    _ => vec![1.0 / tp.len() as f64; tp.len()],
+  };
+
+  let mut out = (0.0, 0.0, 0.0);
+  for c in 0..tp.len() {
+    let (p, r) = (ratio(tp[c], predicted[c]), ratio(tp[c], support[c]));
+    out.0 += weights[c] * p;
+    out.1 += weights[c] * r;
+    out.2 += weights[c] * harmonic(p, r);
+  }
+  Ok(out)
+}
+
+// False positive rate (x) against true positive rate (y) when `class` is the positive one
+pub fn roc_curve(output: &Matrix, labels: &[usize], class: usize) -> Result<Curve, NNErrors> {
+  check_rows(output, labels)?;
+  let positives = labels.iter().filter(|l| **l == class).count() as f64;
+  let negatives = labels.len() as f64 - positives;
+
+  let mut curve = Curve { x: vec![0.0], y: vec![0.0], thresholds: vec![f64::INFINITY] };
+  for (threshold, tp, fp) in ranked(output, labels, class) {
+    curve.x.push(fp / negatives);
+    curve.y.push(tp / positives);
+    curve.thresholds.push(threshold);
+  }
+  Ok(curve)
+}
+
+// Recall (x) against precision (y) when `class` is the positive one
+pub fn pr_curve(output: &Matrix, labels: &[usize], class: usize) -> Result<Curve, NNErrors> {
+  check_rows(output, labels)?;
+  let positives = labels.iter().filter(|l| **l == class).count() as f64;
+
+  let mut curve = Curve::default();
+  for (threshold, tp, fp) in ranked(output, labels, class) {
+    curve.x.push(tp / positives);
+    curve.y.push(tp / (tp + fp));
+    curve.thresholds.push(threshold);
+  }
+  Ok(curve)
+}
+
+// Area under the ROC curve. With more than two classes, the macro average of every class against
+// the rest, over the classes that have both positive and negative rows. Fails when there is no
+// such class, or for binary outputs when the labels hold a single class.
+pub fn roc_auc(output: &Matrix, labels: &[usize]) -> Result<f64, NNErrors> {
+  per_class(output, labels, |class| {
+    let curve = roc_curve(output, labels, class)?;
+    Ok(curve.x.windows(2).zip(curve.y.windows(2)).map(|(x, y)| (x[1] - x[0]) * (y[1] + y[0]) / 2.0).sum())
+  })
+}
+
+// Area under the precision-recall curve, as average precision: the precision at every threshold
+// weighted by the recall gained there. Averaged over classes like `roc_auc`.
+pub fn pr_auc(output: &Matrix, labels: &[usize]) -> Result<f64, NNErrors> {
+  per_class(output, labels, |class| {
+    let curve = pr_curve(output, labels, class)?;
+    let mut last_recall = 0.0;
+    Ok(curve.x.iter().zip(curve.y.iter()).map(|(r, p)| {
+      let area = (r - last_recall) * p;
+      last_recall = *r;
+      area
+    }).sum())
+  })
+}
+
+// Mean negative log-probability of the true class, `output` holding probabilities
+pub fn log_loss(output: &Matrix, labels: &[usize]) -> Result<f64, NNErrors> {
+  check_rows(output, labels)?;
+  let total: f64 = labels.iter().enumerate()
+    .map(|(row, label)| -class_score(output, row, *label).clamp(EPS, 1.0 - EPS).ln())
+    .sum();
+  Ok(total / labels.len() as f64)
+}
+
+// Regression metrics compare `output` with `targets` of the same shape. With several
+// output columns every column is scored on its own and the scores are averaged.
+
+pub fn mae(output: &Matrix, targets: &Matrix) -> Result<f64, NNErrors> {
+  per_column(output, targets, |o, t| {
+    o.iter().zip(t).map(|(o, t)| (o - t).abs()).sum::<f64>() / o.len() as f64
+  })
+}
+
+pub fn rmse(output: &Matrix, targets: &Matrix) -> Result<f64, NNErrors> {
+  per_column(output, targets, |o, t| {
+    (o.iter().zip(t).map(|(o, t)| (o - t).powi(2)).sum::<f64>() / o.len() as f64).sqrt()
+  })
+}
+
+// Coefficient of determination, 1 - residual variance / target variance. Constant targets
+// give 1 when predicted exactly and 0 otherwise.
+pub fn r2(output: &Matrix, targets: &Matrix) -> Result<f64, NNErrors> {
+  per_column(output, targets, |o, t| {
+    let mean = t.iter().sum::<f64>() / t.len() as f64;
+    let ss_res: f64 = o.iter().zip(t).map(|(o, t)| (t - o).powi(2)).sum();
+    let ss_tot: f64 = t.iter().map(|t| (t - mean).powi(2)).sum();
+    explained(ss_res, ss_tot)
+  })
+}
+
+// Like `r2`, but a constant offset between output and targets is not held against the model
+pub fn explained_variance(output: &Matrix, targets: &Matrix) -> Result<f64, NNErrors> {
+  per_column(output, targets, |o, t| {
+    let residuals: Vec<f64> = o.iter().zip(t).map(|(o, t)| t - o).collect();
+    explained(variance(&residuals), variance(t))
+  })
+}
+
+fn check_rows(output: &Matrix, labels: &[usize]) -> Result<(), NNErrors> {
+  if output.nrows() != labels.len() {
+    return Err(NNErrors::RowMismatch(output.nrows(), labels.len()));
+  }
+  Ok(())
+}
+
+fn n_classes(output: &Matrix) -> usize {
+  if output.ncols() == 1 { 2 } else { output.ncols() }
+}
+
+fn class_score(output: &Matrix, row: usize, class: usize) -> f64 {
+  match (output.ncols(), class) {
+    (1, 0) => 1.0 - output[[row, 0]],
+    (1, _) => output[[row, 0]],
+    (_, class) => output.get([row, class]).copied().unwrap_or(f64::NEG_INFINITY),
+  }
+}
+
+// (threshold, true positives, false positives) at every distinct score of `class`, highest first
+fn ranked(output: &Matrix, labels: &[usize], class: usize) -> Vec<(f64, f64, f64)> {
+  let mut rows: Vec<(f64, bool)> = labels.iter().enumerate()
+    .map(|(row, label)| (class_score(output, row, class), *label == class))
+    .collect();
+  rows.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+  let mut out: Vec<(f64, f64, f64)> = Vec::new();
+  let (mut tp, mut fp) = (0.0, 0.0);
+  for (i, (score, positive)) in rows.iter().enumerate() {
+    if *positive { tp += 1.0 } else { fp += 1.0 }
+    if rows.get(i + 1).is_none_or(|next| next.0 != *score) {
+      out.push((*score, tp, fp));
+    }
+  }
+  out
+}
+
+// Binary outputs are scored on class 1 only, others averaged over the classes. A class without
+// positive or without negative rows has no curve and is left out.
+fn per_class(output: &Matrix, labels: &[usize], score: impl Fn(usize) -> Result<f64, NNErrors>) -> Result<f64, NNErrors> {
+  check_rows(output, labels)?;
+  let has_curve = |class: usize| labels.contains(&class) && labels.iter().any(|l| *l != class);
+
+  let classes: Vec<usize> = match n_classes(output) {
+    2 => vec![1].into_iter().filter(|c| has_curve(*c)).collect(),
+    n => (0..n).filter(|c| has_curve(*c)).collect(),
+  };
+  if classes.is_empty() {
+    return Err(NNErrors::UndefinedMetric("no class has both positive and negative rows".to_string()));
+  }
+
+  let scores = classes.into_iter().map(score).collect::<Result<Vec<f64>, NNErrors>>()?;
+  Ok(scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+fn per_column(output: &Matrix, targets: &Matrix, score: impl Fn(&[f64], &[f64]) -> f64) -> Result<f64, NNErrors> {
+  if output.dim() != targets.dim() {
+    return Err(NNErrors::ShapeMismatch(output.shape().to_vec(), targets.shape().to_vec()));
+  }
+
+  let total: f64 = output.columns().into_iter().zip(targets.columns())
+    .map(|(o, t)| score(&o.to_vec(), &t.to_vec()))
+    .sum();
+  Ok(total / output.ncols() as f64)
+}
+
+fn ratio(num: usize, den: usize) -> f64 {
+  if den == 0 { 0.0 } else { num as f64 / den as f64 }
+}
+
+fn harmonic(p: f64, r: f64) -> f64 {
+  if p + r == 0.0 { 0.0 } else { 2.0 * p * r / (p + r) }
+}
+
+fn variance(values: &[f64]) -> f64 {
+  let mean = values.iter().sum::<f64>() / values.len() as f64;
+  values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+fn explained(residual: f64, total: f64) -> f64 {
+  match (residual == 0.0, total == 0.0) {
+    (true, _) => 1.0,
+    (false, true) => 0.0,
+    (false, false) => 1.0 - residual / total,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ndarray::array;
+  use super::*;
+
+  // Seven rows of three classes, predicted [0, 1, 1, 0, 2, 0, 2]
+  fn scores() -> (Matrix, Vec<usize>) {
+    let output = array![
+      [0.7, 0.2, 0.1],
+      [0.3, 0.6, 0.1],
+      [0.15, 0.8, 0.05],
+      [0.5, 0.3, 0.2],
+      [0.1, 0.2, 0.7],
+      [0.5, 0.1, 0.4],
+      [0.1, 0.1, 0.8],
+    ];
+    (output, vec![0, 0, 1, 1, 2, 2, 2])
+  }
+
+  // Sigmoid scores, positives ranked 1st, 2nd and 4th
+  fn ranked_binary() -> (Matrix, Vec<usize>) {
+    (array![[0.9], [0.8], [0.7], [0.6], [0.5], [0.4]], vec![1, 1, 0, 1, 0, 0])
+  }
+
+  fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-12, "{a} != {b}");
+  }
+
+  #[test]
+  fn confusion_matrix_counts_predictions() {
+    let (output, labels) = scores();
+    assert_eq!(predictions(&output), vec![0, 1, 1, 0, 2, 0, 2]);
+    assert_eq!(confusion_matrix(&output, &labels).unwrap(), array![[1, 1, 0], [1, 1, 0], [1, 0, 2]]);
+    assert_close(accuracy(&output, &labels).unwrap(), 4.0 / 7.0);
+
+    let (output, labels) = ranked_binary();
+    assert_eq!(confusion_matrix(&output, &labels).unwrap(), array![[1, 2], [0, 3]]);
+  }
+
+  #[test]
+  fn precision_recall_f1_averages() {
+    // Per class precision 1/3, 1/2, 1, recall 1/2, 1/2, 2/3 and F1 2/5, 1/2, 4/5
+    let (output, labels) = scores();
+    let cases = [
+      (Average::Macro, (11.0 / 18.0, 5.0 / 9.0, 1.7 / 3.0)),
+      (Average::Micro, (4.0 / 7.0, 4.0 / 7.0, 4.0 / 7.0)),
+      (Average::Weighted, (2.0 / 3.0, 4.0 / 7.0, 0.6)),
+    ];
+    for (average, (p, r, f)) in cases {
+      let got = precision_recall_f1(&output, &labels, average).unwrap();
+      assert_close(got.0, p);
+      assert_close(got.1, r);
+      assert_close(got.2, f);
+      assert_close(precision(&output, &labels, average).unwrap(), p);
+      assert_close(recall(&output, &labels, average).unwrap(), r);
+      assert_close(f1(&output, &labels, average).unwrap(), f);
+    }
+  }
+
+  #[test]
+  fn top_k_accuracy_counts_ranks() {
+    let (output, labels) = scores();
+    assert_close(top_k_accuracy(&output, &labels, 1).unwrap(), 4.0 / 7.0);
+    assert_close(top_k_accuracy(&output, &labels, 2).unwrap(), 1.0);
+    assert_close(top_k_accuracy(&output, &labels, 3).unwrap(), 1.0);
+  }
+
+  #[test]
+  fn ranking_metrics() {
+    let (output, labels) = ranked_binary();
+    let roc = roc_curve(&output, &labels, 1).unwrap();
+    assert_eq!(roc.x, vec![0.0, 0.0, 0.0, 1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]);
+    assert_eq!(roc.y, vec![0.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 1.0, 1.0, 1.0]);
+
+    // 8 of the 9 positive-negative pairs are ordered right
+    assert_close(roc_auc(&output, &labels).unwrap(), 8.0 / 9.0);
+    // Precision 1, 1 and 3/4 where each positive is found
+    assert_close(pr_auc(&output, &labels).unwrap(), 11.0 / 12.0);
+
+    // Tied scores form one threshold
+    let tied = array![[0.9], [0.5], [0.5], [0.1]];
+    assert_eq!(roc_curve(&tied, &[1, 1, 0, 0], 1).unwrap().thresholds, vec![f64::INFINITY, 0.9, 0.5, 0.1]);
+    assert_close(roc_auc(&tied, &[1, 1, 0, 0]).unwrap(), 0.875);
+
+    let (output, labels) = scores();
+    let perfect = Matrix::from_shape_fn((7, 3), |(row, class)| f64::from(u8::from(labels[row] == class)));
+    assert_close(roc_auc(&perfect, &labels).unwrap(), 1.0);
+    assert_close(pr_auc(&perfect, &labels).unwrap(), 1.0);
+    assert!(roc_auc(&output, &labels).unwrap() < 1.0);
+  }
+
+  #[test]
+  fn regression_metrics() {
+    // An offset of 1 in the first column, one miss of 1 in the second
+    let targets = array![[1.0, 1.0], [2.0, 2.0], [3.0, 3.0], [4.0, 4.0]];
+    let output = array![[2.0, 1.0], [3.0, 2.0], [4.0, 3.0], [5.0, 5.0]];
+    let column = |i: usize| (output.column(i).to_owned().insert_axis(Axis(1)), targets.column(i).to_owned().insert_axis(Axis(1)));
+
+    let (o, t) = column(0);
+    assert_close(mae(&o, &t).unwrap(), 1.0);
+    assert_close(rmse(&o, &t).unwrap(), 1.0);
+    assert_close(r2(&o, &t).unwrap(), 0.2);
+    assert_close(explained_variance(&o, &t).unwrap(), 1.0);
+
+    let (o, t) = column(1);
+    assert_close(mae(&o, &t).unwrap(), 0.25);
+    assert_close(rmse(&o, &t).unwrap(), 0.5);
+    assert_close(r2(&o, &t).unwrap(), 0.8);
+    assert_close(explained_variance(&o, &t).unwrap(), 0.85);
+
+    assert_close(r2(&output, &targets).unwrap(), 0.5);
+    assert_close(explained_variance(&output, &targets).unwrap(), 0.925);
+
+    let constant = array![[2.0], [2.0]];
+    assert_close(r2(&constant, &constant).unwrap(), 1.0);
+    assert_close(r2(&array![[1.0], [3.0]], &constant).unwrap(), 0.0);
+  }
+
+  #[test]
+  fn ranking_metrics_need_both_outcomes() {
+    let binary = array![[0.9], [0.2], [0.6]];
+    for labels in [[1, 1, 1], [0, 0, 0]] {
+      assert!(matches!(roc_auc(&binary, &labels), Err(NNErrors::UndefinedMetric(_))));
+      assert!(matches!(pr_auc(&binary, &labels), Err(NNErrors::UndefinedMetric(_))));
+    }
+
+    // Class 2 never occurs and is left out of the average instead of turning it into NaN
+    let output = array![[0.8, 0.1, 0.1], [0.3, 0.6, 0.1], [0.6, 0.3, 0.1], [0.2, 0.7, 0.1]];
+    let labels = [0, 1, 0, 1];
+    assert_close(roc_auc(&output, &labels).unwrap(), 1.0);
+    assert_close(pr_auc(&output, &labels).unwrap(), 1.0);
+    assert!(matches!(roc_auc(&output, &[2, 2, 2, 2]), Err(NNErrors::UndefinedMetric(_))));
+  }
+
+  #[test]
+  fn rejects_mismatched_rows_and_shapes() {
+    let (output, labels) = scores();
+    assert!(matches!(accuracy(&output, &labels[1..]), Err(NNErrors::RowMismatch(7, 6))));
+    assert!(matches!(r2(&output, &output.t().to_owned()), Err(NNErrors::ShapeMismatch(..))));
+  }
+}
@@ -1,76 +1,89 @@
-use std::{collections::HashMap, time::Instant};
-#[warn(unused_imports)]
-use std::fmt::{self, Display};
-use ndarray::{Array, Dim};
-use rand::{distributions::Alphanumeric, Rng, thread_rng};
-use ndarray::parallel::prelude::*;
+use std::fmt;
+use std::fs;
+use ndarray::{Array, Dim, Dimension};
+use rand::{RngCore, SeedableRng, rngs::StdRng};
 use serde::{ Deserialize, Serialize };
 use crate::{
   activation::ActivationType,
-  loss::partial_diff_loss,
+  errors::NNErrors,
+  initializer::Initializer,
+  layers::{Activation, Dense, Layer, LayerRecord, Param},
+  loss::Loss,
+  optimizer::Optimizer,
+  preprocessing::Pipeline,
+  regularization::{GradClip, Regularizer, global_norm},
+  tensor::{self, Tensor},
 };
 
-fn random_name() -> String {
-  rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(16)
-        .map(char::from)
-        .collect()
+#[derive(Debug)]
+pub struct Out {
+  // Loss of the training pass the gradients came from, plus the penalties of regularized layers
+  pub error: f64,
+  // Global norm of the gradients, before and after clipping
+  pub grad_norm: f64,
+  pub clipped_norm: f64,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Layer {
-  pub name: String,
-  pub n_input: usize,
-  pub n_output: usize,
-  pub local_weights: HashMap<String, f64>,
-  pub activation: ActivationType
+#[serde(into = "NetworkRecord", try_from = "NetworkRecord")]
+pub struct Network {
+  // Applied in order, the output of each layer is the input of the next one
+  pub layers: Vec<Box<dyn Layer>>,
+  // Drives dropout masks while training
+  rng: StdRng,
 }
 
-impl Display for Layer {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-      writeln!(f, "{} -> {}: {:?}", self.n_input, self.n_output, self.activation)
-    }
+// Version of the saved network layout, bumped whenever it changes
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Json,
+  // MessagePack
+  Binary,
 }
 
-impl Layer {
-  pub fn output(&self, inputs: Array<f64, Dim<[usize; 2]>>) -> Array<f64, Dim<[usize; 2]>> {
-    use ActivationType::*;
+// What `Network::save` writes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedNetwork {
+  pub version: u32,
+  pub network: NetworkRecord,
+  pub optimizer: Option<OptimizerRecord>,
+  // Added in version 2
+  #[serde(default)]
+  pub pipeline: Option<Pipeline>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OptimizerRecord {
+  pub kind: String,
+  pub state: serde_json::Value,
+}
 
-    let weights: Array<f64, _> = self.local_weights
-      .values()
-      .map(|v| v.clone())
-      .collect::<Vec<f64>>()
-      .into();
-    
-    let weights = weights.into_shared().reshape((self.n_output,  inputs.shape()[1]));
-    
-    let original_output = (weights.dot(&(inputs.t()))).into_owned();
-    let original_output = original_output.t().to_owned();
-
-    match self.activation {
-      Step => original_output.to_owned().mapv(|v| if v > 0.5 { 1.0 } else { 0.0 }),
-      Sigmoid => original_output.to_owned().mapv(|v| ActivationType::sigmoid(v)),
-      Tanh => original_output.to_owned().mapv(|v| ActivationType::tanh(v)),
-      ReLU => original_output.to_owned().mapv(|v|  if v >= 0.0 { v } else { 0.0 }),
-      Softmax => ActivationType::softmax(&original_output)
+// Serialized form of a `Network`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NetworkRecord {
+  pub layers: Vec<LayerRecord>,
+}
+
+impl From<Network> for NetworkRecord {
+  fn from(nn: Network) -> Self {
+    NetworkRecord {
+      layers: nn.layers.iter().map(|layer| LayerRecord::from_layer(layer.as_ref())).collect(),
     }
   }
 }
 
-#[derive(Debug)]
-pub struct Out {
-  pub error: f64,
-}
+impl TryFrom<NetworkRecord> for Network {
+  type Error = NNErrors;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Network {
-  pub weights: HashMap<String, HashMap<String, f64>>,
-  pub layers: HashMap<String, Layer>,
-  pub layer_names: Vec<String>,
-  vd: HashMap<String, f64>,
-  sd: HashMap<String, f64>,
-  grads: Vec<Vec<f64>>
+  fn try_from(record: NetworkRecord) -> Result<Self, Self::Error> {
+    let mut nn = Network::empty();
+    for layer in record.layers {
+      nn.push_layer(layer.into_layer()?);
+    }
+    Ok(nn)
+  }
 }
 
 impl fmt::Display for Network {
@@ -80,166 +93,484 @@ impl fmt::Display for Network {
 }
 
 impl Network {
+  // Builds a stack of dense layers, each followed by its activation. Weights are drawn from `rng`
+  // with the scheme suited to the activation (see `Initializer::for_activation`), the dropout
+  // generator is seeded from it too, so a seeded `rng` makes the whole run repeatable.
   pub fn new(
     layers_info: Vec<(&str, usize, usize, ActivationType)>,
+    rng: &mut dyn RngCore,
   ) -> Network {
-    let mut weights: HashMap<String, HashMap<String, f64>> = HashMap::new();
-    let mut layers = HashMap::new();
-    let mut vd = HashMap::new();
-    let mut sd = HashMap::new();
-    let mut grads = Vec::new();
-    let mut layer_names = Vec::new();
-
-    layers_info.to_owned().into_iter().for_each(|(layer_name, n_input, n_output, activation)| {
-      layer_names.push(layer_name.to_string());
-      let mut local_weights = HashMap::new();
-      let mut curr_grads = Vec::new();
-      for _ in 0..(n_input * n_output) {
-          let name = random_name();
-          let value = thread_rng().gen_range(-100000..=100000) as f64 / 250000.0;
-          local_weights.insert(name.to_owned(), value);
-          vd.insert(name.to_owned(), 0.0);
-          sd.insert(name.to_owned(), 0.0);
-          local_weights.insert(name.to_owned(), value); 
-          curr_grads.push(0.0);
-      }
-      let layer = Layer {
-        name: layer_name.to_owned(),
-        n_input,
-        n_output,
-        local_weights: local_weights.clone(),
-        activation,
-      };
-      layers.insert(layer_name.to_string().clone(), layer);
-      weights.insert(layer_name.to_string(), local_weights);
-      grads.push(curr_grads);
+    let mut nn = Network::empty();
+    nn.seed(rng.next_u64());
+
+    layers_info.into_iter().for_each(|(layer_name, n_input, n_output, activation)| {
+      let mut dense = Dense::new(layer_name, n_input, n_output);
+      dense.init(&Initializer::for_activation(activation), rng);
+      nn.add_layer(dense);
+      nn.add_layer(Activation::new(&format!("{layer_name}_activation"), activation));
     });
 
+    nn
+  }
+
+  // The dropout generator starts from a fixed seed, so even networks that are never seeded
+  // (e.g. loaded or imported ones) train the same way every run
+  pub fn empty() -> Network {
     Network {
-      weights,
-      layers,
-      vd,
-      sd,
-      grads,
-      layer_names,
-    }
-  }
-  
-  pub fn import_ws(&mut self, inp_ws: Vec<(String, String, f64)>) {
-      let mut new_weigths: HashMap<String, HashMap<String, f64>> = HashMap::new();
-      
-      inp_ws.clone().into_iter().for_each(|(name_l, name_w, value)| {
-        new_weigths.entry(name_l)
-          .and_modify(|ws| {
-            ws.insert(name_w.clone(), value);
-          })
-          .or_insert(HashMap::from([(name_w, value)]));
-      });
-
-      self.weights = new_weigths.clone();
-
-      inp_ws.clone().into_iter().for_each(|(name_l, _, _)| {
-          self.layers.entry(name_l.clone()).and_modify(|layer| {
-            let mut new_layer = layer.clone();
-            let local_weigths = new_weigths.get(&name_l).unwrap();
-            new_layer.local_weights = local_weigths.clone();
-            
-            *layer = new_layer;
-          });
-      });
+      layers: Vec::new(),
+      rng: StdRng::seed_from_u64(0),
+    }
   }
 
-  pub fn weigth_count(&self) -> usize {
-    self.weights.keys().map(|l| {
-      self.weights.get(l).unwrap().len()
-    }).sum()
+  // Reseeds the generator behind the dropout masks so training runs can be repeated
+  pub fn seed(&mut self, seed: u64) {
+    self.rng = StdRng::seed_from_u64(seed);
   }
 
-  pub fn change_wi(&mut self, name_l: &String, name_w: &String, sub_value: f64) {
-    self.weights.entry(name_l.clone()).and_modify(|ws| {
-      ws.entry(name_w.clone()).and_modify(|val| {
-        *val -= sub_value
-      });
-    });
+  // Appends a layer after the current last one
+  pub fn add_layer<L: Layer + 'static>(&mut self, layer: L) {
+    self.push_layer(Box::new(layer));
+  }
+
+  pub fn push_layer(&mut self, layer: Box<dyn Layer>) {
+    self.layers.push(layer);
+  }
+
+  // Redraws the weights of one layer, e.g. to pick another scheme than `new` did
+  pub fn init_layer(&mut self, name: &str, init: &Initializer, rng: &mut dyn RngCore) -> Result<(), NNErrors> {
+    let layer = self.layers.iter_mut()
+      .find(|layer| layer.name() == name)
+      .ok_or_else(|| NNErrors::NoLayer(name.to_string()))?;
 
-    self.layers.entry(name_l.clone()).and_modify(|layer| {
-      layer.local_weights.entry(name_w.clone()).and_modify(|val| {
-        *val -= sub_value;
-      });
+    layer.init(init, rng);
+    Ok(())
+  }
+
+  // Sets the weight penalty of one layer, `None` removes it
+  pub fn regularize(&mut self, name: &str, regularizer: Option<Regularizer>) -> Result<(), NNErrors> {
+    self.layers.iter_mut()
+      .find(|layer| layer.name() == name)
+      .ok_or_else(|| NNErrors::NoLayer(name.to_string()))?
+      .regularize(regularizer)
+  }
+
+  // Sum of the weight penalties of all layers
+  pub fn penalty(&self) -> f64 {
+    self.layers.iter().map(|layer| layer.penalty()).sum()
+  }
+
+  pub fn layer_names(&self) -> Vec<&str> {
+    self.layers.iter().map(|layer| layer.name()).collect()
+  }
+
+  pub fn layer(&self, name: &str) -> Option<&dyn Layer> {
+    self.layers.iter().find(|layer| layer.name() == name).map(|layer| layer.as_ref())
+  }
+
+  // Weights are addressed by layer name and their position among the layer's flattened parameters,
+  // e.g. for a dense layer the row-major weight matrix followed by the bias
+  pub fn import_ws(&mut self, inp_ws: Vec<(String, usize, f64)>) {
+    inp_ws.into_iter().for_each(|(name_l, w_id, value)| {
+      self.with_param(&name_l, w_id, |w| *w = value);
     });
   }
 
-  pub fn output(&self, vals: &Array<f64, Dim<[usize; 2]>>) -> Array<f64, Dim<[usize; 2]>> {
-    let mut inp = vals.to_owned();
-    for name in self.layer_names.iter() {
-      let layer = self.layers.get(name).unwrap();
-      let new_inp = layer.to_owned().output(inp.to_owned());
-      inp = new_inp;
+  // Parameters of every layer in layer order, as handed to an `Optimizer`
+  pub fn params_mut(&mut self) -> Vec<Param<'_>> {
+    self.layers.iter_mut().flat_map(|layer| layer.params_mut()).collect()
+  }
+
+  pub fn weigth_count(&self) -> usize {
+    self.layers.iter().flat_map(|layer| layer.params()).map(|p| p.len()).sum()
+  }
+
+  pub fn change_wi(&mut self, name_l: &str, w_id: usize, sub_value: f64) {
+    self.with_param(name_l, w_id, |w| *w -= sub_value);
+  }
+
+  fn with_param(&mut self, name_l: &str, w_id: usize, f: impl FnOnce(&mut f64)) {
+    let layer = match self.layers.iter_mut().find(|layer| layer.name() == name_l) {
+      Some(layer) => layer,
+      None => return,
+    };
+
+    let mut w_id = w_id;
+    for mut param in layer.params_mut() {
+      if w_id < param.value.len() {
+        if let Some(w) = param.value.iter_mut().nth(w_id) {
+          f(w);
+        }
+        return;
+      }
+      w_id -= param.value.len();
+    }
+  }
+
+  // Shape of one output sample for input samples of shape `input`, checked layer by layer
+  pub fn output_shape(&self, input: &[usize]) -> Result<Vec<usize>, NNErrors> {
+    self.layers.iter().try_fold(input.to_vec(), |shape, layer| layer.output_shape(&shape))
+  }
+
+  // Inference pass over a batch of samples of any shape, e.g. (batch, features) or
+  // (batch, channels, height, width). The output keeps the shape the last layer gives it.
+  pub fn predict<D: Dimension>(&self, vals: &Array<f64, D>) -> Result<Tensor, NNErrors> {
+    let mut inp = tensor::to_tensor(vals);
+    for layer in self.layers.iter() {
+      inp = layer.output(&inp)?;
+    }
+    Ok(inp)
+  }
+
+  // Like `predict` with every output sample flattened into one row
+  pub fn output<D: Dimension>(&self, vals: &Array<f64, D>) -> Result<Array<f64, Dim<[usize; 2]>>, NNErrors> {
+    tensor::to_rows(&self.predict(vals)?)
+  }
+
+  // Reverse-mode pass: one forward pass in training mode, then one backward pass from
+  // the loss gradient down to the first layer. Every layer keeps the gradients of its
+  // parameters, the network output is returned with every sample flattened into one row.
+  pub fn backprop<D: Dimension>(
+    &mut self,
+    loss: &dyn Loss,
+    values: &Array<f64, D>,
+    answers: &Array<f64, Dim<[usize; 2]>>,
+    sample_weights: Option<&Array<f64, Dim<[usize; 1]>>>,
+  ) -> Result<Array<f64, Dim<[usize; 2]>>, NNErrors> {
+    let mut inp = tensor::to_tensor(values);
+    for layer in self.layers.iter_mut() {
+      inp = layer.forward(&inp, &mut self.rng)?;
+    }
+
+    // Rows have to pair up, which columns the targets hold is up to the loss
+    let out = tensor::to_rows(&inp)?;
+    if out.nrows() != answers.nrows() {
+      return Err(NNErrors::RowMismatch(out.nrows(), answers.nrows()));
+    }
+    if let Some(weights) = sample_weights.filter(|weights| weights.len() != answers.nrows()) {
+      return Err(NNErrors::RowMismatch(weights.len(), answers.nrows()));
+    }
+    loss.check_targets(&out, answers)?;
+
+    let mut grad = tensor::reshape(&loss.backward(&out, answers, sample_weights), inp.shape())?;
+    for layer in self.layers.iter_mut().rev() {
+      grad = layer.backward(&grad)?;
     }
-    inp
+
+    Ok(out)
   }
 
-  pub fn train_layer(
+  pub fn train_layer<D: Dimension>(
     &mut self,
-    loss: &dyn Fn(
-      &Network,
-      &Array<f64, Dim<[usize; 2]>>,
-      &Array<f64, Dim<[usize; 2]>>,
-    ) -> f64,
-    values: &Array<f64, Dim<[usize; 2]>>,
+    loss: &dyn Loss,
+    values: &Array<f64, D>,
     answers: &Array<f64, Dim<[usize; 2]>>,
-    lr: f64,
-  ) -> Out {
-    let betta = 0.9;
-    let gamma = 0.999;
-    let mut gi = 0;
+    sample_weights: Option<&Array<f64, Dim<[usize; 1]>>>,
+    clip: Option<GradClip>,
+    optimizer: &mut dyn Optimizer,
+  ) -> Result<Out, NNErrors> {
+    if let Some(clip) = clip {
+      clip.check()?;
+    }
+    let out = self.backprop(loss, values, answers, sample_weights)?;
+    let error = loss.value(&out, answers, sample_weights) + self.penalty();
 
-    for layer_name in &self.layer_names.clone() {
-      let weights = self.weights.get(layer_name).unwrap();
+    let mut params = self.params_mut();
+    let (grad_norm, clipped_norm) = match clip {
+      Some(clip) => clip.apply(&mut params),
+      None => (global_norm(&params), global_norm(&params)),
+    };
+    optimizer.step(&mut params);
 
-      weights.clone().keys().enumerate().for_each(|(i, key)| {
-        self.vd.entry(key.to_owned()).and_modify(|val| {
-          *val = (val.to_owned() * betta) + (1.0 - betta) * self.grads[gi][i];
-        });
+    Ok(Out {
+      error,
+      grad_norm,
+      clipped_norm,
+    })
+  }
 
-        self.sd.entry(key.to_owned()).and_modify(|val| {
-          *val = (val.to_owned() * gamma) + (1.0 - gamma) * self.grads[gi][i].powf(2.0);
-        });
+  // Saves the layers and their parameters, plus the optimizer state when training is to be resumed
+  // and the fitted preprocessing the network expects its inputs to go through
+  pub fn save(
+    &self,
+    path: &str,
+    format: Format,
+    optimizer: Option<&dyn Optimizer>,
+    pipeline: Option<&Pipeline>,
+  ) -> Result<(), NNErrors> {
+    fs::write(path, self.to_bytes(format, optimizer, pipeline)?)?;
+    Ok(())
+  }
 
-        let powb = 1.0 - betta.powi((i + 1) as i32);
-        let powg = 1.0 - gamma.powi((i + 1) as i32);
+  // Loads a network written by `save`. When `optimizer` is given and the file holds the state
+  // of the same kind of optimizer, that state is loaded into it, same for `pipeline`.
+  pub fn load(
+    path: &str,
+    format: Format,
+    optimizer: Option<&mut dyn Optimizer>,
+    pipeline: Option<&mut Pipeline>,
+  ) -> Result<Network, NNErrors> {
+    Network::from_bytes(&fs::read(path)?, format, optimizer, pipeline)
+  }
 
-        let mt = self.vd.get(&key.to_owned()).unwrap() / powb;
-        let vt = self.sd.get(&key.to_owned()).unwrap() / powg;
+  pub fn to_bytes(
+    &self,
+    format: Format,
+    optimizer: Option<&dyn Optimizer>,
+    pipeline: Option<&Pipeline>,
+  ) -> Result<Vec<u8>, NNErrors> {
+    let saved = SavedNetwork {
+      version: FORMAT_VERSION,
+      network: NetworkRecord::from(self.clone()),
+      optimizer: optimizer.map(|opt| OptimizerRecord { kind: opt.kind().to_string(), state: opt.state() }),
+      pipeline: pipeline.cloned(),
+    };
 
-        let sub_val = lr * mt / (vt.sqrt() + 1e-7);
+    match format {
+      Format::Json => serde_json::to_vec_pretty(&saved).map_err(|e| NNErrors::Format(e.to_string())),
+      Format::Binary => rmp_serde::to_vec_named(&saved).map_err(|e| NNErrors::Format(e.to_string())),
+    }
+  }
 
-        let g = partial_diff_loss(loss, &layer_name.clone(), &key.to_owned(), &self, &values, answers, 1e-4);
+  pub fn from_bytes(
+    bytes: &[u8],
+    format: Format,
+    optimizer: Option<&mut dyn Optimizer>,
+    pipeline: Option<&mut Pipeline>,
+  ) -> Result<Network, NNErrors> {
+    let saved: SavedNetwork = match format {
+      Format::Json => serde_json::from_slice(bytes).map_err(|e| NNErrors::Format(e.to_string()))?,
+      Format::Binary => rmp_serde::from_slice(bytes).map_err(|e| NNErrors::Format(e.to_string()))?,
+    };
 
-        self.change_wi(layer_name, &key.to_owned(), -sub_val);
+    if saved.version > FORMAT_VERSION {
+      return Err(NNErrors::UnsupportedVersion(saved.version));
+    }
 
-        self.grads[gi][i] = g;
-      });
+    // Layers are rebuilt from their config, so parameters of the wrong shape are rejected here
+    let nn = Network::try_from(saved.network)?;
 
-      gi += 1;
+    if let (Some(optimizer), Some(record)) = (optimizer, saved.optimizer) {
+      if record.kind != optimizer.kind() {
+        return Err(NNErrors::OptimizerMismatch(record.kind));
+      }
+      optimizer.load_state(record.state)?;
     }
-    
-    let error = loss(&self, &values, &answers);
-    Out {
-      error
+    if let (Some(pipeline), Some(saved)) = (pipeline, saved.pipeline) {
+      *pipeline = saved;
     }
+
+    Ok(nn)
   }
 
-  pub fn weights_to_vec(&self) -> Vec<(String, String, f64)> {
-      let mut out_vec = Vec::new();
-    
-      for layer_name in &self.layer_names {
-          let layer_ws = self.weights.get(layer_name).unwrap();
-          for (name, value) in layer_ws {
-            out_vec.push((layer_name.clone(), name.clone(), *value));
+  pub fn weights_to_vec(&self) -> Vec<(String, usize, f64)> {
+    let mut out_vec = Vec::new();
+
+    for layer in self.layers.iter() {
+      let values = layer.params().into_iter().flat_map(|p| p.into_iter().copied().collect::<Vec<f64>>());
+      for (w_id, value) in values.enumerate() {
+        out_vec.push((layer.name().to_string(), w_id, value));
+      }
+    }
+
+    out_vec
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{Rng, rngs::StdRng};
+  use crate::{
+    layers::{AvgPool2d, BatchNorm1d, Conv2d, Dropout, GlobalAveragePool, LayerNorm, MaxPool2d, Reshape},
+    loss::Mse,
+    optimizer::{Adam, Sgd},
+  };
+  use super::*;
+
+  // Loss of one training-mode pass. The clone replays the dropout masks of `nn`.
+  fn training_loss<D: Dimension>(nn: &Network, inputs: &Array<f64, D>, targets: &Array<f64, Dim<[usize; 2]>>) -> f64 {
+    let out = nn.clone().backprop(&Mse::new(), inputs, targets, None).unwrap();
+    Mse::new().value(&out, targets, None)
+  }
+
+  // Draws every parameter from (-1, 1), so scales and shifts are not at their identity values
+  fn randomize(nn: &mut Network, rng: &mut StdRng) {
+    for mut param in nn.params_mut() {
+      param.value.mapv_inplace(|_| rng.gen_range(-1.0..1.0));
+    }
+  }
+
+  // Compares the gradients `backprop` leaves in every parameter with central differences
+  fn check_gradients<D: Dimension>(nn: &Network, inputs: &Array<f64, D>, n_outputs: usize, rng: &mut StdRng) {
+    let targets = Array::from_shape_fn((inputs.shape()[0], n_outputs), |_| rng.gen_range(-1.0..1.0));
+
+    let mut trained = nn.clone();
+    trained.backprop(&Mse::new(), inputs, &targets, None).unwrap();
+    let grads: Vec<Vec<f64>> = trained.params_mut().iter().map(|p| p.grad.iter().copied().collect()).collect();
+
+    let eps = 1e-6;
+    for (k, grad) in grads.iter().enumerate() {
+      for (i, g) in grad.iter().enumerate() {
+        let shifted = |delta: f64| {
+          let mut nn = nn.clone();
+          if let Some(w) = nn.params_mut()[k].value.iter_mut().nth(i) {
+            *w += delta;
           }
+          training_loss(&nn, inputs, &targets)
+        };
+        let numeric = (shifted(eps) - shifted(-eps)) / (2.0 * eps);
+        assert!((numeric - g).abs() < 1e-6, "param {k}[{i}]: {numeric} vs {g}");
       }
+    }
+  }
+
+  #[test]
+  fn dense_gradients_through_every_activation() {
+    use ActivationType::*;
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let inputs = Array::from_shape_fn((5, 3), |_| rng.gen_range(-2.0..2.0));
+    for activation in [
+      Step, Sigmoid, Tanh, ReLU, LeakyReLU(0.1), ELU(0.8), GELU, SiLU, Softplus, Mish, HardSigmoid, Linear, Softmax,
+    ] {
+      let mut nn = Network::empty();
+      nn.add_layer(Dense::new("hidden", 3, 4));
+      nn.add_layer(Activation::new("activation", activation));
+      nn.add_layer(Dense::new("out", 4, 2));
+      randomize(&mut nn, &mut rng);
+      check_gradients(&nn, &inputs, 2, &mut rng);
+    }
+  }
+
+  #[test]
+  fn image_gradients() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut nn = Network::empty();
+    nn.add_layer(Conv2d::new("conv1", 2, 3, 3, 1, 1, (5, 5)).unwrap());
+    nn.add_layer(Activation::new("tanh", ActivationType::Tanh));
+    nn.add_layer(MaxPool2d::new("max", 3, (5, 5), 2, 1).unwrap());
+    nn.add_layer(AvgPool2d::new("avg", 3, (4, 4), 2, 2).unwrap());
+    nn.add_layer(Conv2d::new("conv2", 3, 4, 2, 1, 1, (2, 2)).unwrap());
+    nn.add_layer(GlobalAveragePool::new("gap", 4, (3, 3)));
+    nn.add_layer(Dense::new("out", 4, 2));
+    randomize(&mut nn, &mut rng);
+
+    assert_eq!(nn.output_shape(&[2, 5, 5]).unwrap(), vec![2]);
+    let inputs = Array::from_shape_fn((3, 2, 5, 5), |_| rng.gen_range(-1.0..1.0));
+    check_gradients(&nn, &inputs, 2, &mut rng);
+  }
+
+  #[test]
+  fn sequence_norm_and_dropout_gradients() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut nn = Network::empty();
+    nn.seed(7);
+    nn.add_layer(Dense::new("embed", 5, 6));
+    nn.add_layer(LayerNorm::new("layer_norm", 6));
+    nn.add_layer(Dropout::new("dropout", 0.3).unwrap());
+    nn.add_layer(BatchNorm1d::new("batch_norm", 6));
+    nn.add_layer(Activation::new("gelu", ActivationType::GELU));
+    nn.add_layer(Reshape::new("reshape", &[3, 6], &[18]).unwrap());
+    nn.add_layer(Dense::new("out", 18, 2));
+    randomize(&mut nn, &mut rng);
+
+    assert_eq!(nn.output_shape(&[3, 5]).unwrap(), vec![2]);
+    let inputs = Array::from_shape_fn((4, 3, 5), |_| rng.gen_range(-1.0..1.0));
+    check_gradients(&nn, &inputs, 2, &mut rng);
+  }
+
+  fn small_network(rng: &mut StdRng) -> Network {
+    let mut nn = Network::empty();
+    nn.add_layer(Dense::new("hidden", 3, 4));
+    nn.add_layer(Activation::new("tanh", ActivationType::Tanh));
+    nn.add_layer(Dense::new("out", 4, 2));
+    randomize(&mut nn, rng);
+    nn
+  }
+
+  fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("rust-nn-{}-{name}", std::process::id())).to_string_lossy().into_owned()
+  }
+
+  #[test]
+  fn save_and_load_keep_the_output() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let nn = small_network(&mut rng);
+    let inputs = Array::from_shape_fn((5, 3), |_| rng.gen_range(-1.0..1.0));
+
+    for (format, name) in [(Format::Json, "network.json"), (Format::Binary, "network.msgpack")] {
+      let path = temp_path(name);
+      nn.save(&path, format, None, None).unwrap();
+      let loaded = Network::load(&path, format, None, None);
+      fs::remove_file(&path).unwrap();
+
+      let loaded = loaded.unwrap();
+      assert_eq!(loaded.layer_names(), nn.layer_names());
+      assert_eq!(loaded.output(&inputs).unwrap(), nn.output(&inputs).unwrap());
+    }
+  }
+
+  #[test]
+  fn resumed_training_matches_an_uninterrupted_run() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut nn = small_network(&mut rng);
+    let inputs = Array::from_shape_fn((6, 3), |_| rng.gen_range(-1.0..1.0));
+    let targets = Array::from_shape_fn((6, 2), |_| rng.gen_range(-1.0..1.0));
+
+    let mut adam = Adam::new(0.05);
+    for _ in 0..3 {
+      nn.train_layer(&Mse::new(), &inputs, &targets, None, None, &mut adam).unwrap();
+    }
+
+    for format in [Format::Json, Format::Binary] {
+      let bytes = nn.to_bytes(format, Some(&adam), None).unwrap();
+      let mut resumed_adam = Adam::new(1.0);
+      let mut resumed = Network::from_bytes(&bytes, format, Some(&mut resumed_adam), None).unwrap();
+
+      let (mut uninterrupted, mut adam) = (nn.clone(), adam.clone());
+      uninterrupted.train_layer(&Mse::new(), &inputs, &targets, None, None, &mut adam).unwrap();
+      resumed.train_layer(&Mse::new(), &inputs, &targets, None, None, &mut resumed_adam).unwrap();
+      assert_eq!(resumed.weights_to_vec(), uninterrupted.weights_to_vec());
+    }
+  }
+
+  #[test]
+  fn load_rejects_mismatched_records() {
+    let nn = small_network(&mut StdRng::seed_from_u64(6));
+    let bytes = nn.to_bytes(Format::Binary, Some(&Adam::new(0.1)), None).unwrap();
+    let saved: SavedNetwork = rmp_serde::from_slice(&bytes).unwrap();
+    let tampered = |edit: &dyn Fn(&mut SavedNetwork)| {
+      let mut saved = saved.clone();
+      edit(&mut saved);
+      Network::from_bytes(&rmp_serde::to_vec_named(&saved).unwrap(), Format::Binary, None, None)
+    };
+
+    let mut sgd = Sgd::new(0.1);
+    let err = Network::from_bytes(&bytes, Format::Binary, Some(&mut sgd), None);
+    assert!(matches!(err, Err(NNErrors::OptimizerMismatch(kind)) if kind == "adam"));
+
+    let err = tampered(&|saved| saved.version = FORMAT_VERSION + 1);
+    assert!(matches!(err, Err(NNErrors::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1));
+
+    // Weights of a (2, 3) layer where a (4, 3) one is configured
+    let err = tampered(&|saved| saved.network.layers[0].params[0] = ndarray::ArrayD::zeros(vec![2, 3]));
+    assert!(matches!(err, Err(NNErrors::ParamMismatch(name)) if name == "hidden"));
+    let err = tampered(&|saved| { saved.network.layers[2].params.pop(); });
+    assert!(matches!(err, Err(NNErrors::ParamMismatch(name)) if name == "out"));
+
+    assert!(matches!(Network::from_bytes(&bytes, Format::Json, None, None), Err(NNErrors::Format(_))));
+  }
+
+  #[test]
+  fn train_layer_reports_the_loss_before_the_update() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut nn = small_network(&mut rng);
+    nn.regularize("out", Some(Regularizer::l2(0.1).unwrap())).unwrap();
+    let inputs = Array::from_shape_fn((6, 3), |_| rng.gen_range(-1.0..1.0));
+    let targets = Array::from_shape_fn((6, 2), |_| rng.gen_range(-1.0..1.0));
 
-      out_vec
+    let before = Mse::new().value(&nn.output(&inputs).unwrap(), &targets, None) + nn.penalty();
+    let out = nn.train_layer(&Mse::new(), &inputs, &targets, None, None, &mut Sgd::new(0.5)).unwrap();
+    assert!((out.error - before).abs() < 1e-12, "{} != {before}", out.error);
+    assert!(Mse::new().value(&nn.output(&inputs).unwrap(), &targets, None) + nn.penalty() < before);
   }
 }
@@ -0,0 +1,384 @@
+mod proto;
+
+use std::collections::HashMap;
+use std::fs;
+use ndarray::{Array, Dim};
+use prost::Message;
+use crate::{
+  activation::ActivationType,
+  errors::NNErrors,
+  layers::{Activation, Dense, Dropout, LayerRecord},
+  network::Network,
+};
+use self::proto::*;
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+const IR_VERSION: i64 = 8;
+const OPSET_VERSION: i64 = 13;
+
+// Writes a network of dense and activation layers as an ONNX model taking a
+// (batch, features) float tensor named "input". Dropout is left out as it does nothing at inference.
+pub fn save(nn: &Network, path: &str) -> Result<(), NNErrors> {
+  fs::write(path, export(nn)?)?;
+  Ok(())
+}
+
+pub fn load(path: &str) -> Result<Network, NNErrors> {
+  import(&fs::read(path)?)
+}
+
+pub fn export(nn: &Network) -> Result<Vec<u8>, NNErrors> {
+  let mut graph = GraphProto { name: "rust-nn".to_string(), ..Default::default() };
+  let mut current = "input".to_string();
+  let mut first_input = None;
+  let mut width = 0;
+
+  for layer in nn.layers.iter() {
+    let record = LayerRecord::from_layer(layer.as_ref());
+    let name = layer.name().to_string();
+
+    let node = match record.kind.as_str() {
+      Dense::KIND => {
+        let (weights, bias) = (&record.params[0], &record.params[1]);
+        let (n_output, n_input) = (weights.shape()[0], weights.shape()[1]);
+        if width != 0 && width != n_input {
+          return Err(NNErrors::Onnx(format!("layer {name} takes {n_input} inputs but gets {width}")));
+        }
+        first_input.get_or_insert(n_input);
+        width = n_output;
+
+        let weight_name = format!("{name}.weight");
+        let bias_name = format!("{name}.bias");
+        graph.initializer.push(tensor(&weight_name, weights.shape(), weights.iter()));
+        graph.initializer.push(tensor(&bias_name, bias.shape(), bias.iter()));
+
+        NodeProto {
+          input: vec![current.clone(), weight_name, bias_name],
+          op_type: "Gemm".to_string(),
+          attribute: vec![int_attribute("transB", 1)],
+          ..Default::default()
+        }
+      }
+      Activation::KIND => {
+        let activation: ActivationType = serde_json::from_value(record.config["activation"].clone())
+          .map_err(|e| NNErrors::LayerConfig(e.to_string()))?;
+        let (op_type, attribute) = match activation {
+          ActivationType::Sigmoid => ("Sigmoid", vec![]),
+          ActivationType::Tanh => ("Tanh", vec![]),
+          ActivationType::ReLU => ("Relu", vec![]),
+          ActivationType::LeakyReLU(slope) => ("LeakyRelu", vec![float_attribute("alpha", slope)]),
+          ActivationType::ELU(alpha) => ("Elu", vec![float_attribute("alpha", alpha)]),
+          ActivationType::Softplus => ("Softplus", vec![]),
+          ActivationType::HardSigmoid => (
+            "HardSigmoid",
+            vec![float_attribute("alpha", 1.0 / 6.0), float_attribute("beta", 0.5)],
+          ),
+          ActivationType::Softmax => ("Softmax", vec![int_attribute("axis", 1)]),
+          ActivationType::Linear => continue,
+          other => return Err(NNErrors::Onnx(format!("no ONNX operator for {other:?} in layer {name}"))),
+        };
+
+        NodeProto {
+          input: vec![current.clone()],
+          op_type: op_type.to_string(),
+          attribute,
+          ..Default::default()
+        }
+      }
+      Dropout::KIND => continue,
+      kind => return Err(NNErrors::Onnx(format!("layer {name} of kind {kind} can not be exported"))),
+    };
+
+    current = name.clone();
+    graph.node.push(NodeProto { name: name.clone(), output: vec![name], ..node });
+  }
+
+  let n_input = first_input.ok_or_else(|| NNErrors::Onnx("network has no dense layer".to_string()))?;
+  graph.input.push(value_info("input", n_input));
+  graph.output.push(value_info(&current, width));
+
+  let model = ModelProto {
+    ir_version: IR_VERSION,
+    opset_import: vec![OperatorSetIdProto { domain: String::new(), version: OPSET_VERSION }],
+    producer_name: "rust-nn".to_string(),
+    producer_version: env!("CARGO_PKG_VERSION").to_string(),
+    graph: Some(graph),
+  };
+
+  Ok(model.encode_to_vec())
+}
+
+// Rebuilds a network from an ONNX model made of a single chain of Gemm or MatMul (+ Add) nodes
+// with constant weights and supported activation nodes
+pub fn import(bytes: &[u8]) -> Result<Network, NNErrors> {
+  let model = ModelProto::decode(bytes).map_err(|e| NNErrors::Onnx(e.to_string()))?;
+  let graph = model.graph.ok_or_else(|| NNErrors::Onnx("model has no graph".to_string()))?;
+
+  let mut initializers = HashMap::new();
+  for init in graph.initializer.iter() {
+    initializers.insert(init.name.as_str(), tensor_values(init)?);
+  }
+  let constant = |name: &str| {
+    initializers.get(name).ok_or_else(|| NNErrors::Onnx(format!("{name} is not a constant")))
+  };
+
+  let mut current = match graph.input.iter().find(|input| !initializers.contains_key(input.name.as_str())) {
+    Some(input) => input.name.clone(),
+    None => return Err(NNErrors::Onnx("model has no input".to_string())),
+  };
+
+  let mut nn = Network::empty();
+  // The last MatMul, whose bias may come from the next Add node
+  let mut pending: Option<Dense> = None;
+
+  for (id, node) in graph.node.iter().enumerate() {
+    let name = if node.name.is_empty() { format!("{}_{id}", node.op_type) } else { node.name.clone() };
+    let data_input = node.input.iter().position(|input| *input == current)
+      .ok_or_else(|| NNErrors::Onnx(format!("node {name} does not follow the previous one")))?;
+    let output = node.output.first()
+      .ok_or_else(|| NNErrors::Onnx(format!("node {name} has no output")))?;
+
+    if node.op_type != "Add" {
+      if let Some(dense) = pending.take() {
+        nn.add_layer(dense);
+      }
+    }
+
+    match node.op_type.as_str() {
+      "Gemm" => {
+        if data_input != 0 || int_attr(node, "transA", 0) != 0 {
+          return Err(NNErrors::Onnx(format!("node {name}: only Gemm(input, weight, bias) is supported")));
+        }
+        let (dims, values) = constant(node.input.get(1).map_or("", |s| s.as_str()))?;
+        let mut weights = matrix(&name, dims, values)?;
+        if int_attr(node, "transB", 0) == 0 {
+          weights = weights.reversed_axes().as_standard_layout().to_owned();
+        }
+        weights *= float_attr(node, "alpha", 1.0);
+
+        let mut dense = Dense::new(&name, weights.ncols(), weights.nrows());
+        if let Some(bias) = node.input.get(2).filter(|b| !b.is_empty()) {
+          let (_, values) = constant(bias)?;
+          set_bias(&mut dense, values, float_attr(node, "beta", 1.0))?;
+        }
+        dense.weights = weights;
+        nn.add_layer(dense);
+      }
+      "MatMul" => {
+        if data_input != 0 {
+          return Err(NNErrors::Onnx(format!("node {name}: only MatMul(input, weight) is supported")));
+        }
+        let (dims, values) = constant(node.input.get(1).map_or("", |s| s.as_str()))?;
+        let weights = matrix(&name, dims, values)?.reversed_axes().as_standard_layout().to_owned();
+
+        let mut dense = Dense::new(&name, weights.ncols(), weights.nrows());
+        dense.weights = weights;
+        pending = Some(dense);
+      }
+      "Add" => {
+        let mut dense = pending.take()
+          .ok_or_else(|| NNErrors::Onnx(format!("node {name}: Add is only supported right after a MatMul")))?;
+        let (_, values) = constant(node.input.get(1 - data_input.min(1)).map_or("", |s| s.as_str()))?;
+        set_bias(&mut dense, values, 1.0)?;
+        nn.add_layer(dense);
+      }
+      "Sigmoid" | "Tanh" | "Relu" | "LeakyRelu" | "Elu" | "Softplus" | "HardSigmoid" | "Softmax" => {
+        let activation = match node.op_type.as_str() {
+          "Sigmoid" => ActivationType::Sigmoid,
+          "Tanh" => ActivationType::Tanh,
+          "Relu" => ActivationType::ReLU,
+          "LeakyRelu" => ActivationType::LeakyReLU(float_attr(node, "alpha", 0.01)),
+          "Elu" => ActivationType::ELU(float_attr(node, "alpha", 1.0)),
+          "Softplus" => ActivationType::Softplus,
+          "HardSigmoid" => {
+            // Only the x / 6 + 0.5 variant exists on our side
+            let (alpha, beta) = (float_attr(node, "alpha", 0.2), float_attr(node, "beta", 0.5));
+            if (alpha - 1.0 / 6.0).abs() > 1e-6 || (beta - 0.5).abs() > 1e-6 {
+              return Err(NNErrors::Onnx(format!("node {name}: HardSigmoid needs alpha 1/6 and beta 0.5")));
+            }
+            ActivationType::HardSigmoid
+          }
+          _ => ActivationType::Softmax,
+        };
+        nn.add_layer(Activation::new(&name, activation));
+      }
+      "Identity" | "Dropout" => {}
+      other => return Err(NNErrors::Onnx(format!("node {name}: unsupported operator {other}"))),
+    }
+
+    current = output.clone();
+  }
+
+  if let Some(dense) = pending {
+    nn.add_layer(dense);
+  }
+  if nn.layers.is_empty() {
+    return Err(NNErrors::Onnx("model has no supported nodes".to_string()));
+  }
+
+  Ok(nn)
+}
+
+fn tensor<'a>(name: &str, dims: &[usize], values: impl Iterator<Item = &'a f64>) -> TensorProto {
+  TensorProto {
+    name: name.to_string(),
+    dims: dims.iter().map(|d| *d as i64).collect(),
+    data_type: TENSOR_FLOAT,
+    raw_data: values.flat_map(|v| (*v as f32).to_le_bytes()).collect(),
+    ..Default::default()
+  }
+}
+
+fn tensor_values(tensor: &TensorProto) -> Result<(Vec<usize>, Vec<f64>), NNErrors> {
+  let dims = tensor.dims.iter().map(|d| *d as usize).collect();
+
+  let values = match tensor.data_type {
+    TENSOR_FLOAT if !tensor.raw_data.is_empty() => tensor.raw_data.chunks_exact(4)
+      .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+      .collect(),
+    TENSOR_FLOAT => tensor.float_data.iter().map(|v| *v as f64).collect(),
+    TENSOR_DOUBLE if !tensor.raw_data.is_empty() => tensor.raw_data.chunks_exact(8)
+      .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+      .collect(),
+    TENSOR_DOUBLE => tensor.double_data.clone(),
+    other => return Err(NNErrors::Onnx(format!("{}: unsupported tensor type {other}", tensor.name))),
+  };
+
+  Ok((dims, values))
+}
+
+fn matrix(name: &str, dims: &[usize], values: &[f64]) -> Result<Matrix, NNErrors> {
+  match dims {
+    [rows, cols] => Array::from_shape_vec((*rows, *cols), values.to_vec())
+      .map_err(|e| NNErrors::Onnx(format!("node {name}: {e}"))),
+    _ => Err(NNErrors::Onnx(format!("node {name}: weights must be a matrix, got shape {dims:?}"))),
+  }
+}
+
+fn set_bias(dense: &mut Dense, values: &[f64], scale: f64) -> Result<(), NNErrors> {
+  if values.len() != dense.n_output {
+    return Err(NNErrors::Onnx(format!("node {}: bias of {} values for {} outputs", dense.name, values.len(), dense.n_output)));
+  }
+  dense.bias = Array::from_iter(values.iter().map(|v| v * scale));
+  Ok(())
+}
+
+fn int_attribute(name: &str, i: i64) -> AttributeProto {
+  AttributeProto { name: name.to_string(), i, r#type: ATTRIBUTE_INT, ..Default::default() }
+}
+
+fn float_attribute(name: &str, f: f64) -> AttributeProto {
+  AttributeProto { name: name.to_string(), f: f as f32, r#type: ATTRIBUTE_FLOAT, ..Default::default() }
+}
+
+fn int_attr(node: &NodeProto, name: &str, default: i64) -> i64 {
+  node.attribute.iter().find(|a| a.name == name).map_or(default, |a| a.i)
+}
+
+fn float_attr(node: &NodeProto, name: &str, default: f64) -> f64 {
+  node.attribute.iter()
+    .find(|a| a.name == name && a.r#type == ATTRIBUTE_FLOAT)
+    .map_or(default, |a| a.f as f64)
+}
+
+fn value_info(name: &str, width: usize) -> ValueInfoProto {
+  let dim = vec![
+    Dimension { dim_param: Some("batch".to_string()), ..Default::default() },
+    Dimension { dim_value: Some(width as i64), ..Default::default() },
+  ];
+
+  ValueInfoProto {
+    name: name.to_string(),
+    r#type: Some(TypeProto {
+      tensor_type: Some(TensorTypeProto { elem_type: TENSOR_FLOAT, shape: Some(TensorShapeProto { dim }) }),
+    }),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ndarray::array;
+  use rand::{Rng, SeedableRng, rngs::StdRng};
+  use crate::{initializer::Initializer, layers::Layer};
+  use super::*;
+
+  // Weights go through f32 on the way
+  const TOL: f64 = 1e-5;
+
+  fn max_diff(a: &Matrix, b: &Matrix) -> f64 {
+    assert_eq!(a.dim(), b.dim());
+    (a - b).mapv(f64::abs).fold(0.0, |m, v| m.max(*v))
+  }
+
+  fn model(nodes: Vec<NodeProto>, initializer: Vec<TensorProto>, n_input: usize) -> Vec<u8> {
+    let graph = GraphProto { node: nodes, initializer, input: vec![value_info("x", n_input)], ..Default::default() };
+    ModelProto { ir_version: IR_VERSION, graph: Some(graph), ..Default::default() }.encode_to_vec()
+  }
+
+  fn node(op_type: &str, input: &[&str], output: &str) -> NodeProto {
+    NodeProto {
+      name: output.to_string(),
+      op_type: op_type.to_string(),
+      input: input.iter().map(|s| s.to_string()).collect(),
+      output: vec![output.to_string()],
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn round_trip_keeps_every_exportable_activation() {
+    use ActivationType::*;
+
+    let mut rng = StdRng::seed_from_u64(5);
+    let inputs = Array::from_shape_fn((6, 4), |_| rng.gen_range(-3.0..3.0));
+    for activation in [Sigmoid, Tanh, ReLU, LeakyReLU(0.2), ELU(0.7), Softplus, HardSigmoid, Softmax, Linear] {
+      let mut nn = Network::empty();
+      let mut hidden = Dense::new("hidden", 4, 5);
+      hidden.init(&Initializer::GlorotUniform, &mut rng);
+      hidden.bias.mapv_inplace(|_| rng.gen_range(-1.0..1.0));
+      nn.add_layer(hidden);
+      nn.add_layer(Activation::new("hidden_activation", activation));
+      nn.add_layer(Dropout::new("dropout", 0.5).unwrap());
+      let mut out = Dense::new("out", 5, 3);
+      out.init(&Initializer::GlorotUniform, &mut rng);
+      nn.add_layer(out);
+
+      let imported = import(&export(&nn).unwrap()).unwrap();
+      let diff = max_diff(&nn.output(&inputs).unwrap(), &imported.output(&inputs).unwrap());
+      assert!(diff < TOL, "{activation:?}: {diff}");
+    }
+  }
+
+  #[test]
+  fn imports_matmul_followed_by_add() {
+    let weights = array![[0.5, -1.0], [2.0, 0.25], [-0.75, 1.5]];
+    let bias = array![0.1, -0.2];
+    let bytes = model(
+      vec![node("MatMul", &["x", "w"], "h"), node("Add", &["b", "h"], "z"), node("Relu", &["z"], "y")],
+      vec![tensor("w", weights.shape(), weights.iter()), tensor("b", bias.shape(), bias.iter())],
+      3,
+    );
+
+    let nn = import(&bytes).unwrap();
+    assert_eq!(nn.layer_names(), vec!["h", "y"]);
+    let inputs = array![[1.0, 2.0, 3.0], [-1.0, 0.5, 0.0]];
+    let expected = (inputs.dot(&weights) + &bias).mapv(|v| v.max(0.0));
+    assert!(max_diff(&nn.output(&inputs).unwrap(), &expected) < TOL);
+  }
+
+  #[test]
+  fn rejects_unsupported_operators() {
+    let weights = array![[1.0, 0.0], [0.0, 1.0]];
+    let bytes = model(
+      vec![node("MatMul", &["x", "w"], "h"), node("Conv", &["h"], "y")],
+      vec![tensor("w", weights.shape(), weights.iter())],
+      2,
+    );
+
+    match import(&bytes) {
+      Err(NNErrors::Onnx(message)) => assert!(message.contains("unsupported operator Conv"), "{message}"),
+      other => panic!("expected an ONNX error, got {other:?}"),
+    }
+  }
+}
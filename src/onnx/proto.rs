@@ -0,0 +1,124 @@
+// The part of onnx.proto the exporter and importer need, field tags follow the official schema.
+// Fields of a `oneof` are spelled out as plain optional fields, which is the same on the wire.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelProto {
+  #[prost(int64, tag = "1")]
+  pub ir_version: i64,
+  #[prost(message, repeated, tag = "8")]
+  pub opset_import: Vec<OperatorSetIdProto>,
+  #[prost(string, tag = "2")]
+  pub producer_name: String,
+  #[prost(string, tag = "3")]
+  pub producer_version: String,
+  #[prost(message, optional, tag = "7")]
+  pub graph: Option<GraphProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OperatorSetIdProto {
+  #[prost(string, tag = "1")]
+  pub domain: String,
+  #[prost(int64, tag = "2")]
+  pub version: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GraphProto {
+  #[prost(message, repeated, tag = "1")]
+  pub node: Vec<NodeProto>,
+  #[prost(string, tag = "2")]
+  pub name: String,
+  #[prost(message, repeated, tag = "5")]
+  pub initializer: Vec<TensorProto>,
+  #[prost(message, repeated, tag = "11")]
+  pub input: Vec<ValueInfoProto>,
+  #[prost(message, repeated, tag = "12")]
+  pub output: Vec<ValueInfoProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NodeProto {
+  #[prost(string, repeated, tag = "1")]
+  pub input: Vec<String>,
+  #[prost(string, repeated, tag = "2")]
+  pub output: Vec<String>,
+  #[prost(string, tag = "3")]
+  pub name: String,
+  #[prost(string, tag = "4")]
+  pub op_type: String,
+  #[prost(message, repeated, tag = "5")]
+  pub attribute: Vec<AttributeProto>,
+  #[prost(string, tag = "7")]
+  pub domain: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AttributeProto {
+  #[prost(string, tag = "1")]
+  pub name: String,
+  #[prost(float, tag = "2")]
+  pub f: f32,
+  #[prost(int64, tag = "3")]
+  pub i: i64,
+  #[prost(int32, tag = "20")]
+  pub r#type: i32,
+}
+
+pub const ATTRIBUTE_FLOAT: i32 = 1;
+pub const ATTRIBUTE_INT: i32 = 2;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TensorProto {
+  #[prost(int64, repeated, packed = "false", tag = "1")]
+  pub dims: Vec<i64>,
+  #[prost(int32, tag = "2")]
+  pub data_type: i32,
+  #[prost(float, repeated, tag = "4")]
+  pub float_data: Vec<f32>,
+  #[prost(string, tag = "8")]
+  pub name: String,
+  #[prost(bytes = "vec", tag = "9")]
+  pub raw_data: Vec<u8>,
+  #[prost(double, repeated, tag = "10")]
+  pub double_data: Vec<f64>,
+}
+
+pub const TENSOR_FLOAT: i32 = 1;
+pub const TENSOR_DOUBLE: i32 = 11;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ValueInfoProto {
+  #[prost(string, tag = "1")]
+  pub name: String,
+  #[prost(message, optional, tag = "2")]
+  pub r#type: Option<TypeProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TypeProto {
+  #[prost(message, optional, tag = "1")]
+  pub tensor_type: Option<TensorTypeProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TensorTypeProto {
+  #[prost(int32, tag = "1")]
+  pub elem_type: i32,
+  #[prost(message, optional, tag = "2")]
+  pub shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TensorShapeProto {
+  #[prost(message, repeated, tag = "1")]
+  pub dim: Vec<Dimension>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Dimension {
+  #[prost(int64, optional, tag = "1")]
+  pub dim_value: Option<i64>,
+  #[prost(string, optional, tag = "2")]
+  pub dim_param: Option<String>,
+}
@@ -0,0 +1,425 @@
+use std::fmt;
+use ndarray::{Array, ArrayD, Zip};
+use serde::{ Deserialize, Serialize, de::DeserializeOwned };
+use crate::{errors::NNErrors, layers::Param};
+
+// Updates parameters from their gradients. `params` always holds the same parameters in the
+// same order (see `Network::params_mut`), so per-parameter state is kept by position.
+pub trait Optimizer: fmt::Debug + Send {
+  fn step(&mut self, params: &mut [Param<'_>]);
+
+  fn learning_rate(&self) -> f64;
+
+  fn set_learning_rate(&mut self, lr: f64);
+
+  // Tag the state is saved under, so it is only loaded back into the same optimizer
+  fn kind(&self) -> &'static str;
+
+  // Hyperparameters together with the per-parameter state, enough to resume training
+  fn state(&self) -> serde_json::Value;
+
+  fn load_state(&mut self, state: serde_json::Value) -> Result<(), NNErrors>;
+}
+
+fn to_state<T: Serialize>(optimizer: &T) -> serde_json::Value {
+  serde_json::to_value(optimizer).expect("optimizer state is always serializable")
+}
+
+fn from_state<T: DeserializeOwned>(state: serde_json::Value) -> Result<T, NNErrors> {
+  serde_json::from_value(state).map_err(|e| NNErrors::Format(e.to_string()))
+}
+
+// Makes sure there is one zeroed state array per parameter
+fn init_state(state: &mut Vec<ArrayD<f64>>, params: &[Param<'_>]) {
+  let fits = state.len() == params.len()
+    && state.iter().zip(params.iter()).all(|(s, p)| s.shape() == p.value.shape());
+
+  if !fits {
+    *state = params.iter().map(|p| Array::zeros(p.value.raw_dim())).collect();
+  }
+}
+
+// Stochastic gradient descent, optionally with (Nesterov) momentum
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Sgd {
+  pub lr: f64,
+  pub momentum: f64,
+  pub nesterov: bool,
+  velocity: Vec<ArrayD<f64>>,
+}
+
+impl Sgd {
+  pub fn new(lr: f64) -> Sgd {
+    Sgd { lr, momentum: 0.0, nesterov: false, velocity: Vec::new() }
+  }
+
+  pub fn momentum(lr: f64, momentum: f64) -> Sgd {
+    Sgd { momentum, ..Sgd::new(lr) }
+  }
+
+  pub fn nesterov(lr: f64, momentum: f64) -> Sgd {
+    Sgd { momentum, nesterov: true, ..Sgd::new(lr) }
+  }
+}
+
+impl Optimizer for Sgd {
+  fn step(&mut self, params: &mut [Param<'_>]) {
+    let (lr, mu, nesterov) = (self.lr, self.momentum, self.nesterov);
+
+    if mu == 0.0 {
+      params.iter_mut().for_each(|p| {
+        Zip::from(&mut p.value).and(&p.grad).for_each(|w, g| *w -= lr * g);
+      });
+      return;
+    }
+
+    init_state(&mut self.velocity, params);
+    params.iter_mut().zip(self.velocity.iter_mut()).for_each(|(p, v)| {
+      Zip::from(&mut p.value).and(&p.grad).and(v).for_each(|w, g, v| {
+        *v = mu * *v + g;
+        *w -= lr * if nesterov { g + mu * *v } else { *v };
+      });
+    });
+  }
+
+  fn learning_rate(&self) -> f64 {
+    self.lr
+  }
+
+  fn set_learning_rate(&mut self, lr: f64) {
+    self.lr = lr;
+  }
+
+  fn kind(&self) -> &'static str {
+    "sgd"
+  }
+
+  fn state(&self) -> serde_json::Value {
+    to_state(self)
+  }
+
+  fn load_state(&mut self, state: serde_json::Value) -> Result<(), NNErrors> {
+    *self = from_state(state)?;
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RmsProp {
+  pub lr: f64,
+  // Decay of the running average of squared gradients
+  pub rho: f64,
+  pub eps: f64,
+  sq_avg: Vec<ArrayD<f64>>,
+}
+
+impl RmsProp {
+  pub fn new(lr: f64) -> RmsProp {
+    RmsProp { lr, rho: 0.9, eps: 1e-8, sq_avg: Vec::new() }
+  }
+}
+
+impl Optimizer for RmsProp {
+  fn step(&mut self, params: &mut [Param<'_>]) {
+    let (lr, rho, eps) = (self.lr, self.rho, self.eps);
+
+    init_state(&mut self.sq_avg, params);
+    params.iter_mut().zip(self.sq_avg.iter_mut()).for_each(|(p, s)| {
+      Zip::from(&mut p.value).and(&p.grad).and(s).for_each(|w, g, s| {
+        *s = rho * *s + (1.0 - rho) * g * g;
+        *w -= lr * g / (s.sqrt() + eps);
+      });
+    });
+  }
+
+  fn learning_rate(&self) -> f64 {
+    self.lr
+  }
+
+  fn set_learning_rate(&mut self, lr: f64) {
+    self.lr = lr;
+  }
+
+  fn kind(&self) -> &'static str {
+    "rmsprop"
+  }
+
+  fn state(&self) -> serde_json::Value {
+    to_state(self)
+  }
+
+  fn load_state(&mut self, state: serde_json::Value) -> Result<(), NNErrors> {
+    *self = from_state(state)?;
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Adagrad {
+  pub lr: f64,
+  pub eps: f64,
+  sq_sum: Vec<ArrayD<f64>>,
+}
+
+impl Adagrad {
+  pub fn new(lr: f64) -> Adagrad {
+    Adagrad { lr, eps: 1e-8, sq_sum: Vec::new() }
+  }
+}
+
+impl Optimizer for Adagrad {
+  fn step(&mut self, params: &mut [Param<'_>]) {
+    let (lr, eps) = (self.lr, self.eps);
+
+    init_state(&mut self.sq_sum, params);
+    params.iter_mut().zip(self.sq_sum.iter_mut()).for_each(|(p, s)| {
+      Zip::from(&mut p.value).and(&p.grad).and(s).for_each(|w, g, s| {
+        *s += g * g;
+        *w -= lr * g / (s.sqrt() + eps);
+      });
+    });
+  }
+
+  fn learning_rate(&self) -> f64 {
+    self.lr
+  }
+
+  fn set_learning_rate(&mut self, lr: f64) {
+    self.lr = lr;
+  }
+
+  fn kind(&self) -> &'static str {
+    "adagrad"
+  }
+
+  fn state(&self) -> serde_json::Value {
+    to_state(self)
+  }
+
+  fn load_state(&mut self, state: serde_json::Value) -> Result<(), NNErrors> {
+    *self = from_state(state)?;
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Adam {
+  pub lr: f64,
+  pub beta1: f64,
+  pub beta2: f64,
+  pub eps: f64,
+  // Number of steps taken, drives the bias correction
+  t: i32,
+  m: Vec<ArrayD<f64>>,
+  v: Vec<ArrayD<f64>>,
+}
+
+impl Adam {
+  pub fn new(lr: f64) -> Adam {
+    Adam { lr, beta1: 0.9, beta2: 0.999, eps: 1e-8, t: 0, m: Vec::new(), v: Vec::new() }
+  }
+
+  // Adam step with the weights shrunk by `decay` beforehand
+  fn update(&mut self, params: &mut [Param<'_>], decay: f64) {
+    let (lr, b1, b2, eps) = (self.lr, self.beta1, self.beta2, self.eps);
+
+    init_state(&mut self.m, params);
+    init_state(&mut self.v, params);
+    self.t += 1;
+
+    let powb1 = 1.0 - b1.powi(self.t);
+    let powb2 = 1.0 - b2.powi(self.t);
+
+    params.iter_mut().zip(self.m.iter_mut().zip(self.v.iter_mut())).for_each(|(p, (m, v))| {
+      Zip::from(&mut p.value).and(&p.grad).and(m).and(v).for_each(|w, g, m, v| {
+        *m = b1 * *m + (1.0 - b1) * g;
+        *v = b2 * *v + (1.0 - b2) * g * g;
+
+        *w -= lr * decay * *w;
+        *w -= lr * (*m / powb1) / ((*v / powb2).sqrt() + eps);
+      });
+    });
+  }
+}
+
+impl Optimizer for Adam {
+  fn step(&mut self, params: &mut [Param<'_>]) {
+    self.update(params, 0.0);
+  }
+
+  fn learning_rate(&self) -> f64 {
+    self.lr
+  }
+
+  fn set_learning_rate(&mut self, lr: f64) {
+    self.lr = lr;
+  }
+
+  fn kind(&self) -> &'static str {
+    "adam"
+  }
+
+  fn state(&self) -> serde_json::Value {
+    to_state(self)
+  }
+
+  fn load_state(&mut self, state: serde_json::Value) -> Result<(), NNErrors> {
+    *self = from_state(state)?;
+    Ok(())
+  }
+}
+
+// Adam with decoupled weight decay
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdamW {
+  pub adam: Adam,
+  pub weight_decay: f64,
+}
+
+impl AdamW {
+  pub fn new(lr: f64, weight_decay: f64) -> AdamW {
+    AdamW { adam: Adam::new(lr), weight_decay }
+  }
+}
+
+impl Optimizer for AdamW {
+  fn step(&mut self, params: &mut [Param<'_>]) {
+    self.adam.update(params, self.weight_decay);
+  }
+
+  fn learning_rate(&self) -> f64 {
+    self.adam.lr
+  }
+
+  fn set_learning_rate(&mut self, lr: f64) {
+    self.adam.lr = lr;
+  }
+
+  fn kind(&self) -> &'static str {
+    "adamw"
+  }
+
+  fn state(&self) -> serde_json::Value {
+    to_state(self)
+  }
+
+  fn load_state(&mut self, state: serde_json::Value) -> Result<(), NNErrors> {
+    *self = from_state(state)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ndarray::{ArrayD, array};
+  use super::*;
+
+  // Weights 1 and -2, the gradient of the second one always twice as large and of opposite sign
+  struct Weights {
+    value: ArrayD<f64>,
+    grad: ArrayD<f64>,
+  }
+
+  impl Weights {
+    fn new() -> Weights {
+      Weights { value: array![1.0, -2.0].into_dyn(), grad: ArrayD::zeros(vec![2]) }
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer, grad: f64) -> [f64; 2] {
+      self.grad.assign(&array![grad, -2.0 * grad].into_dyn());
+      optimizer.step(&mut [Param { value: self.value.view_mut(), grad: self.grad.view_mut() }]);
+      [self.value[0], self.value[1]]
+    }
+  }
+
+  fn close(got: [f64; 2], expected: [f64; 2]) {
+    assert!(got.iter().zip(expected).all(|(g, e)| (g - e).abs() < 1e-12), "{got:?} != {expected:?}");
+  }
+
+  #[test]
+  fn sgd() {
+    let (mut w, mut sgd) = (Weights::new(), Sgd::new(0.1));
+    close(w.step(&mut sgd, 0.5), [0.95, -1.9]);
+    close(w.step(&mut sgd, 0.5), [0.9, -1.8]);
+
+    // Velocity 0.5, then 0.9 * 0.5 + 0.5
+    let (mut w, mut sgd) = (Weights::new(), Sgd::momentum(0.1, 0.9));
+    close(w.step(&mut sgd, 0.5), [0.95, -1.9]);
+    close(w.step(&mut sgd, 0.5), [0.855, -1.71]);
+  }
+
+  #[test]
+  fn sgd_nesterov() {
+    // Steps along g + 0.9 * v: 0.5 + 0.45, then 0.5 + 0.9 * 0.95
+    let (mut w, mut sgd) = (Weights::new(), Sgd::nesterov(0.1, 0.9));
+    close(w.step(&mut sgd, 0.5), [0.905, -1.81]);
+    close(w.step(&mut sgd, 0.5), [0.7695, -1.539]);
+  }
+
+  #[test]
+  fn rms_prop() {
+    // Running averages 0.1 g^2 and 0.19 g^2
+    let (mut w, mut rms) = (Weights::new(), RmsProp { eps: 0.0, ..RmsProp::new(0.01) });
+    let first = [1.0 - 0.01 / 0.1f64.sqrt(), -2.0 + 0.01 / 0.1f64.sqrt()];
+    close(w.step(&mut rms, 0.5), first);
+    close(w.step(&mut rms, 0.5), [first[0] - 0.01 / 0.19f64.sqrt(), first[1] + 0.01 / 0.19f64.sqrt()]);
+  }
+
+  #[test]
+  fn adagrad() {
+    // Sums g^2, then 2 g^2
+    let (mut w, mut adagrad) = (Weights::new(), Adagrad { eps: 0.0, ..Adagrad::new(0.1) });
+    close(w.step(&mut adagrad, 0.5), [0.9, -1.9]);
+    close(w.step(&mut adagrad, 0.5), [0.9 - 0.1 / 2f64.sqrt(), -1.9 + 0.1 / 2f64.sqrt()]);
+  }
+
+  #[test]
+  fn adam_corrects_bias_by_step_count() {
+    let (mut w, mut adam) = (Weights::new(), Adam { eps: 0.0, ..Adam::new(0.1) });
+    // After one step the corrected moments are exactly g and g^2
+    close(w.step(&mut adam, 0.5), [0.9, -1.9]);
+
+    // Second step with gradient 1.5, corrected by 1 - beta^2
+    let m = 0.9 * 0.05 + 0.1 * 1.5;
+    let v = 0.999 * 0.001 * 0.25 + 0.001 * 2.25;
+    let update = 0.1 * (m / (1.0 - 0.9f64.powi(2))) / (v / (1.0 - 0.999f64.powi(2))).sqrt();
+    close(w.step(&mut adam, 1.5), [0.9 - update, -1.9 + update]);
+  }
+
+  #[test]
+  fn adam_w_decays_apart_from_the_gradient() {
+    let mut adam_w = AdamW::new(0.1, 0.01);
+    adam_w.adam.eps = 0.0;
+    let mut w = Weights::new();
+    // Weights shrink by lr * decay, then take the plain Adam step of 0.1
+    close(w.step(&mut adam_w, 0.5), [0.999 - 0.1, -1.998 + 0.1]);
+    close(w.step(&mut adam_w, 0.5), [0.899 * 0.999 - 0.1, -1.898 * 0.999 + 0.1]);
+
+    // Without a gradient only the decay is left
+    let mut w = Weights::new();
+    close(w.step(&mut AdamW::new(0.1, 0.01), 0.0), [0.999, -1.998]);
+  }
+
+  #[test]
+  fn resumed_optimizer_takes_the_same_step() {
+    let optimizers: Vec<(Box<dyn Optimizer>, Box<dyn Optimizer>)> = vec![
+      (Box::new(Sgd::nesterov(0.1, 0.9)), Box::new(Sgd::new(1.0))),
+      (Box::new(RmsProp::new(0.01)), Box::new(RmsProp::new(1.0))),
+      (Box::new(Adagrad::new(0.1)), Box::new(Adagrad::new(1.0))),
+      (Box::new(Adam::new(0.1)), Box::new(Adam::new(1.0))),
+      (Box::new(AdamW::new(0.1, 0.01)), Box::new(AdamW::new(1.0, 0.0))),
+    ];
+
+    for (mut optimizer, mut resumed) in optimizers {
+      let mut w = Weights::new();
+      w.step(optimizer.as_mut(), 0.5);
+      w.step(optimizer.as_mut(), -0.3);
+
+      resumed.load_state(optimizer.state()).unwrap();
+      let mut copy = Weights { value: w.value.clone(), grad: w.grad.clone() };
+      close(copy.step(resumed.as_mut(), 0.8), w.step(optimizer.as_mut(), 0.8));
+    }
+
+    assert!(matches!(Adam::new(0.1).load_state(Sgd::new(0.1).state()), Err(NNErrors::Format(_))));
+  }
+}
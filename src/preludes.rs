@@ -1,7 +1,7 @@
 use ndarray::{Array, Dim};
 
 pub fn argmax<T: Copy + PartialOrd>(u: &[T]) -> (usize, T) {
-  assert!(u.len() != 0);
+  assert!(!u.is_empty());
   let mut max_index = 0;
   let mut max = u[max_index];
   for (i, v) in (u.iter()).enumerate() {
@@ -29,7 +29,7 @@ pub fn vec_to_array<T: Clone>(v: Vec<Vec<T>>) -> Array<T, Dim<[usize; 2]>> {
   let mut data = Vec::with_capacity(nrows * ncols);
   for row in &v {
       assert_eq!(row.len(), ncols);
-      data.extend_from_slice(&row);
+      data.extend_from_slice(row);
   }
   Array::from_shape_vec((nrows, ncols), data).unwrap()
 }
\ No newline at end of file
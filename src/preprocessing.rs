@@ -0,0 +1,599 @@
+use serde::{ Deserialize, Serialize };
+use crate::{
+  data_processing::{Col, Series, Values},
+  errors::NNErrors,
+};
+
+// Learns its parameters from training data with `fit`, then applies them to any data with the same columns
+pub trait Transformer {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors>;
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors>;
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors>;
+
+  fn fit_transform(&mut self, series: &Series) -> Result<Series, NNErrors> {
+    self.fit(series)?;
+    self.transform(series)
+  }
+}
+
+// (x - mean) / std
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct StandardScaler {
+  pub cols: Vec<String>,
+  pub mean: Vec<f64>,
+  pub std: Vec<f64>,
+}
+
+impl StandardScaler {
+  pub fn new(cols: &[String]) -> StandardScaler {
+    StandardScaler { cols: cols.to_vec(), ..Default::default() }
+  }
+}
+
+impl Transformer for StandardScaler {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    let stats = self.cols.iter().map(|name| {
+      let values = present(series, name)?;
+      let mean = values.iter().sum::<f64>() / values.len() as f64;
+      let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / values.len() as f64;
+      Ok((mean, non_zero(var.sqrt())))
+    }).collect::<Result<Vec<_>, NNErrors>>()?;
+
+    (self.mean, self.std) = stats.into_iter().unzip();
+    Ok(())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("StandardScaler", &self.cols, &self.mean)?;
+    map_numeric(series, &self.cols, |i, x| (x - self.mean[i]) / self.std[i])
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("StandardScaler", &self.cols, &self.mean)?;
+    map_numeric(series, &self.cols, |i, x| x * self.std[i] + self.mean[i])
+  }
+}
+
+// Maps [min, max] of the training data onto [0, 1]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MinMaxScaler {
+  pub cols: Vec<String>,
+  pub min: Vec<f64>,
+  pub max: Vec<f64>,
+}
+
+impl MinMaxScaler {
+  pub fn new(cols: &[String]) -> MinMaxScaler {
+    MinMaxScaler { cols: cols.to_vec(), ..Default::default() }
+  }
+
+  fn range(&self, i: usize) -> f64 {
+    non_zero(self.max[i] - self.min[i])
+  }
+}
+
+impl Transformer for MinMaxScaler {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    let stats = self.cols.iter().map(|name| {
+      let values = present(series, name)?;
+      Ok((values.iter().copied().fold(f64::INFINITY, f64::min), values.iter().copied().fold(f64::NEG_INFINITY, f64::max)))
+    }).collect::<Result<Vec<_>, NNErrors>>()?;
+
+    (self.min, self.max) = stats.into_iter().unzip();
+    Ok(())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("MinMaxScaler", &self.cols, &self.min)?;
+    map_numeric(series, &self.cols, |i, x| (x - self.min[i]) / self.range(i))
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("MinMaxScaler", &self.cols, &self.min)?;
+    map_numeric(series, &self.cols, |i, x| x * self.range(i) + self.min[i])
+  }
+}
+
+// (x - median) / interquartile range, barely moved by outliers
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RobustScaler {
+  pub cols: Vec<String>,
+  pub median: Vec<f64>,
+  pub iqr: Vec<f64>,
+}
+
+impl RobustScaler {
+  pub fn new(cols: &[String]) -> RobustScaler {
+    RobustScaler { cols: cols.to_vec(), ..Default::default() }
+  }
+}
+
+impl Transformer for RobustScaler {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    let stats = self.cols.iter().map(|name| {
+      let values = sorted(present(series, name)?);
+      Ok((quantile(&values, 0.5), non_zero(quantile(&values, 0.75) - quantile(&values, 0.25))))
+    }).collect::<Result<Vec<_>, NNErrors>>()?;
+
+    (self.median, self.iqr) = stats.into_iter().unzip();
+    Ok(())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("RobustScaler", &self.cols, &self.median)?;
+    map_numeric(series, &self.cols, |i, x| (x - self.median[i]) / self.iqr[i])
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("RobustScaler", &self.cols, &self.median)?;
+    map_numeric(series, &self.cols, |i, x| x * self.iqr[i] + self.median[i])
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum ImputeStrategy {
+  Mean,
+  Median,
+  Constant(f64),
+}
+
+// Fills missing numeric values. Which values were missing is not kept, so `inverse_transform`
+// leaves the data as it is.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Imputer {
+  pub cols: Vec<String>,
+  pub strategy: ImputeStrategy,
+  pub fill: Vec<f64>,
+}
+
+impl Imputer {
+  pub fn new(cols: &[String], strategy: ImputeStrategy) -> Imputer {
+    Imputer { cols: cols.to_vec(), strategy, fill: Vec::new() }
+  }
+}
+
+impl Transformer for Imputer {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    self.fill = self.cols.iter().map(|name| {
+      let values = present(series, name)?;
+      Ok(match self.strategy {
+        ImputeStrategy::Mean => values.iter().sum::<f64>() / values.len() as f64,
+        ImputeStrategy::Median => quantile(&sorted(values), 0.5),
+        ImputeStrategy::Constant(value) => value,
+      })
+    }).collect::<Result<_, NNErrors>>()?;
+    Ok(())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("Imputer", &self.cols, &self.fill)?;
+
+    let mut out = series.clone();
+    for (i, name) in self.cols.iter().enumerate() {
+      let values = series.col(name)?.floats()?;
+      out.push_col(Col::new(name, Values::Float(values.into_iter().map(|x| Some(x.unwrap_or(self.fill[i]))).collect())))?;
+    }
+    Ok(out)
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    Ok(series.clone())
+  }
+}
+
+// Replaces every value by the index of its category, categories being sorted.
+// A label encoder is the same thing applied to the target column.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct OrdinalEncoder {
+  pub cols: Vec<String>,
+  pub categories: Vec<Vec<String>>,
+}
+
+pub type LabelEncoder = OrdinalEncoder;
+
+impl OrdinalEncoder {
+  pub fn new(cols: &[String]) -> OrdinalEncoder {
+    OrdinalEncoder { cols: cols.to_vec(), ..Default::default() }
+  }
+}
+
+impl Transformer for OrdinalEncoder {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    self.categories = self.cols.iter().map(|name| series.unique_in_col(name)).collect::<Result<_, _>>()?;
+    Ok(())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("OrdinalEncoder", &self.cols, &self.categories)?;
+
+    let mut out = series.clone();
+    for (name, categories) in self.cols.iter().zip(self.categories.iter()) {
+      let codes = encode(series.col(name)?, categories)?;
+      out.push_col(Col::new(name, Values::Int(codes.into_iter().map(|c| c.map(|c| c as i64)).collect())))?;
+    }
+    Ok(out)
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("OrdinalEncoder", &self.cols, &self.categories)?;
+
+    let mut out = series.clone();
+    for (name, categories) in self.cols.iter().zip(self.categories.iter()) {
+      let cells = series.col(name)?.floats()?.into_iter()
+        .map(|c| c.and_then(|c| categories.get(c.round() as usize).cloned()).unwrap_or_default())
+        .collect::<Vec<String>>();
+      out.push_col(Col::parse(name, &cells))?;
+    }
+    Ok(out)
+  }
+}
+
+// Replaces a column by one 0 / 1 column per category, named `<column>=<category>`.
+// A missing value gives all zeros.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct OneHotEncoder {
+  pub cols: Vec<String>,
+  pub categories: Vec<Vec<String>>,
+}
+
+impl OneHotEncoder {
+  pub fn new(cols: &[String]) -> OneHotEncoder {
+    OneHotEncoder { cols: cols.to_vec(), ..Default::default() }
+  }
+
+  fn column_names(name: &str, categories: &[String]) -> Vec<String> {
+    categories.iter().map(|c| format!("{name}={c}")).collect()
+  }
+}
+
+impl Transformer for OneHotEncoder {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    self.categories = self.cols.iter().map(|name| series.unique_in_col(name)).collect::<Result<_, _>>()?;
+    Ok(())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("OneHotEncoder", &self.cols, &self.categories)?;
+
+    let mut cols = Vec::new();
+    for header in series.headers.iter() {
+      let col = series.col(header)?;
+      match self.cols.iter().position(|c| c == header) {
+        Some(i) => {
+          let codes = encode(col, &self.categories[i])?;
+          for (k, name) in OneHotEncoder::column_names(header, &self.categories[i]).into_iter().enumerate() {
+            let values = codes.iter().map(|c| Some(if *c == Some(k) { 1.0 } else { 0.0 })).collect();
+            cols.push(Col::new(&name, Values::Float(values)));
+          }
+        }
+        None => cols.push(col.clone()),
+      }
+    }
+    Series::from_cols(cols)
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    fitted("OneHotEncoder", &self.cols, &self.categories)?;
+
+    let mut cols = Vec::new();
+    let mut done = vec![false; self.cols.len()];
+    for header in series.headers.iter() {
+      let i = self.cols.iter().zip(self.categories.iter())
+        .position(|(name, categories)| OneHotEncoder::column_names(name, categories).contains(header));
+
+      match i {
+        Some(i) if done[i] => {}
+        Some(i) => {
+          let names = OneHotEncoder::column_names(&self.cols[i], &self.categories[i]);
+          let one_hot = names.iter().map(|n| series.col(n)?.floats()).collect::<Result<Vec<_>, _>>()?;
+
+          // The category with the highest score wins, so probabilities decode too
+          let cells = (0..series.n_rows()).map(|row| {
+            let (k, best) = one_hot.iter().enumerate()
+              .map(|(k, col)| (k, col[row].unwrap_or(0.0)))
+              .fold((0, 0.0), |best, cur| if cur.1 > best.1 { cur } else { best });
+            if best > 0.0 { self.categories[i][k].clone() } else { String::new() }
+          }).collect::<Vec<String>>();
+
+          cols.push(Col::parse(&self.cols[i], &cells));
+          done[i] = true;
+        }
+        None => cols.push(series.col(header)?.clone()),
+      }
+    }
+    Series::from_cols(cols)
+  }
+}
+
+// One step of a `Pipeline`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum Transform {
+  Standard(StandardScaler),
+  MinMax(MinMaxScaler),
+  Robust(RobustScaler),
+  Impute(Imputer),
+  Ordinal(OrdinalEncoder),
+  OneHot(OneHotEncoder),
+}
+
+impl Transform {
+  fn inner(&self) -> &dyn Transformer {
+    match self {
+      Transform::Standard(t) => t,
+      Transform::MinMax(t) => t,
+      Transform::Robust(t) => t,
+      Transform::Impute(t) => t,
+      Transform::Ordinal(t) => t,
+      Transform::OneHot(t) => t,
+    }
+  }
+
+  fn inner_mut(&mut self) -> &mut dyn Transformer {
+    match self {
+      Transform::Standard(t) => t,
+      Transform::MinMax(t) => t,
+      Transform::Robust(t) => t,
+      Transform::Impute(t) => t,
+      Transform::Ordinal(t) => t,
+      Transform::OneHot(t) => t,
+    }
+  }
+}
+
+impl From<StandardScaler> for Transform {
+  fn from(t: StandardScaler) -> Self {
+    Transform::Standard(t)
+  }
+}
+
+impl From<MinMaxScaler> for Transform {
+  fn from(t: MinMaxScaler) -> Self {
+    Transform::MinMax(t)
+  }
+}
+
+impl From<RobustScaler> for Transform {
+  fn from(t: RobustScaler) -> Self {
+    Transform::Robust(t)
+  }
+}
+
+impl From<Imputer> for Transform {
+  fn from(t: Imputer) -> Self {
+    Transform::Impute(t)
+  }
+}
+
+impl From<OrdinalEncoder> for Transform {
+  fn from(t: OrdinalEncoder) -> Self {
+    Transform::Ordinal(t)
+  }
+}
+
+impl From<OneHotEncoder> for Transform {
+  fn from(t: OneHotEncoder) -> Self {
+    Transform::OneHot(t)
+  }
+}
+
+// Steps run in order, each one fitted on the output of the previous ones
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Pipeline {
+  pub steps: Vec<Transform>,
+}
+
+impl Pipeline {
+  pub fn new() -> Pipeline {
+    Pipeline::default()
+  }
+
+  pub fn add(&mut self, step: impl Into<Transform>) {
+    self.steps.push(step.into());
+  }
+}
+
+impl Transformer for Pipeline {
+  fn fit(&mut self, series: &Series) -> Result<(), NNErrors> {
+    self.fit_transform(series).map(|_| ())
+  }
+
+  fn transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    self.steps.iter().try_fold(series.clone(), |data, step| step.inner().transform(&data))
+  }
+
+  fn inverse_transform(&self, series: &Series) -> Result<Series, NNErrors> {
+    self.steps.iter().rev().try_fold(series.clone(), |data, step| step.inner().inverse_transform(&data))
+  }
+
+  fn fit_transform(&mut self, series: &Series) -> Result<Series, NNErrors> {
+    self.steps.iter_mut().try_fold(series.clone(), |data, step| step.inner_mut().fit_transform(&data))
+  }
+}
+
+// Applies `f(i, x)` to the present values of the i-th of `cols`
+fn map_numeric(series: &Series, cols: &[String], f: impl Fn(usize, f64) -> f64) -> Result<Series, NNErrors> {
+  let mut out = series.clone();
+  for (i, name) in cols.iter().enumerate() {
+    let mut col = series.col(name)?.clone();
+    col.map_floats(|x| f(i, x))?;
+    out.push_col(col)?;
+  }
+  Ok(out)
+}
+
+fn fitted<T>(name: &'static str, cols: &[String], params: &[T]) -> Result<(), NNErrors> {
+  if cols.len() != params.len() {
+    return Err(NNErrors::NotFitted(name));
+  }
+  Ok(())
+}
+
+fn present(series: &Series, name: &str) -> Result<Vec<f64>, NNErrors> {
+  Ok(series.col(name)?.floats()?.into_iter().flatten().collect())
+}
+
+// Scales of 0 (constant columns) would divide by zero, those are left unscaled
+fn non_zero(scale: f64) -> f64 {
+  if scale == 0.0 || !scale.is_finite() { 1.0 } else { scale }
+}
+
+fn sorted(mut values: Vec<f64>) -> Vec<f64> {
+  values.sort_by(f64::total_cmp);
+  values
+}
+
+// Linear interpolation between the closest ranks of sorted `values`
+fn quantile(values: &[f64], q: f64) -> f64 {
+  if values.is_empty() {
+    return f64::NAN;
+  }
+  let pos = q * (values.len() - 1) as f64;
+  let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+  values[lo] + (values[hi] - values[lo]) * (pos - lo as f64)
+}
+
+// Category index of every cell, in the order of `categories`
+fn encode(col: &Col, categories: &[String]) -> Result<Vec<Option<usize>>, NNErrors> {
+  (0..col.len()).map(|row| match col.get(row) {
+    Some(value) => categories.iter().position(|c| *c == value)
+      .map(Some)
+      .ok_or_else(|| NNErrors::UnknownCategory(col.name.clone(), value)),
+    None => Ok(None),
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::network::{Format, Network};
+  use super::*;
+
+  fn names(cols: &[&str]) -> Vec<String> {
+    cols.iter().map(|c| c.to_string()).collect()
+  }
+
+  fn data() -> Series {
+    Series::from_cols(vec![
+      Col::new("x", Values::Float(vec![Some(1.0), Some(2.5), Some(4.0), Some(10.0), Some(-3.0)])),
+      Col::new("n", Values::Int(vec![Some(3), Some(1), Some(4), Some(1), Some(5)])),
+      Col::new("colour", Values::Str(["red", "blue", "red", "green", "blue"].map(|c| Some(c.to_string())).to_vec())),
+    ]).unwrap()
+  }
+
+  fn floats(series: &Series, name: &str) -> Vec<f64> {
+    series.col(name).unwrap().dense_floats().unwrap()
+  }
+
+  fn strings(series: &Series, name: &str) -> Vec<Option<String>> {
+    let col = series.col(name).unwrap();
+    (0..col.len()).map(|row| col.get(row)).collect()
+  }
+
+  fn assert_close(a: &[f64], b: &[f64]) {
+    assert_eq!(a.len(), b.len());
+    assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9), "{a:?} != {b:?}");
+  }
+
+  // fit_transform then inverse_transform gives the numeric columns back
+  fn round_trip(mut scaler: impl Transformer) -> Series {
+    let series = data();
+    let scaled = scaler.fit_transform(&series).unwrap();
+    let restored = scaler.inverse_transform(&scaled).unwrap();
+    for name in ["x", "n"] {
+      assert_close(&floats(&restored, name), &floats(&series, name));
+    }
+    assert_eq!(strings(&restored, "colour"), strings(&series, "colour"));
+    scaled
+  }
+
+  #[test]
+  fn standard_scaler() {
+    let scaled = round_trip(StandardScaler::new(&names(&["x", "n"])));
+    for name in ["x", "n"] {
+      let values = floats(&scaled, name);
+      let mean = values.iter().sum::<f64>() / values.len() as f64;
+      let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+      assert!(mean.abs() < 1e-12 && (var - 1.0).abs() < 1e-12);
+    }
+  }
+
+  #[test]
+  fn min_max_scaler() {
+    let scaled = round_trip(MinMaxScaler::new(&names(&["x", "n"])));
+    assert_close(&floats(&scaled, "x"), &[4.0 / 13.0, 5.5 / 13.0, 7.0 / 13.0, 1.0, 0.0]);
+    assert_close(&floats(&scaled, "n"), &[0.5, 0.0, 0.75, 0.0, 1.0]);
+  }
+
+  #[test]
+  fn robust_scaler() {
+    let scaled = round_trip(RobustScaler::new(&names(&["x"])));
+    // Median 2.5, quartiles 1 and 4
+    assert_close(&floats(&scaled, "x"), &[-0.5, 0.0, 0.5, 2.5, -11.0 / 6.0]);
+  }
+
+  #[test]
+  fn imputer() {
+    let series = Series::from_cols(vec![Col::new("x", Values::Float(vec![Some(1.0), None, Some(2.0), Some(6.0)]))]).unwrap();
+    for (strategy, fill) in [(ImputeStrategy::Mean, 3.0), (ImputeStrategy::Median, 2.0), (ImputeStrategy::Constant(-1.0), -1.0)] {
+      let mut imputer = Imputer::new(&names(&["x"]), strategy);
+      let filled = imputer.fit_transform(&series).unwrap();
+      assert_close(&floats(&filled, "x"), &[1.0, fill, 2.0, 6.0]);
+      assert_close(&floats(&imputer.inverse_transform(&filled).unwrap(), "x"), &[1.0, fill, 2.0, 6.0]);
+    }
+  }
+
+  #[test]
+  fn ordinal_encoder() {
+    let series = data();
+    let mut encoder = OrdinalEncoder::new(&names(&["colour"]));
+    let encoded = encoder.fit_transform(&series).unwrap();
+    assert_eq!(encoder.categories, vec![names(&["blue", "green", "red"])]);
+    assert_close(&floats(&encoded, "colour"), &[2.0, 0.0, 2.0, 1.0, 0.0]);
+    assert_eq!(strings(&encoder.inverse_transform(&encoded).unwrap(), "colour"), strings(&series, "colour"));
+
+    let unseen = Series::from_cols(vec![Col::new("colour", Values::Str(vec![Some("pink".to_string())]))]).unwrap();
+    assert!(matches!(encoder.transform(&unseen), Err(NNErrors::UnknownCategory(..))));
+  }
+
+  #[test]
+  fn one_hot_encoder() {
+    let series = data();
+    let mut encoder = OneHotEncoder::new(&names(&["colour"]));
+    let encoded = encoder.fit_transform(&series).unwrap();
+    assert_eq!(encoded.headers, names(&["x", "n", "colour=blue", "colour=green", "colour=red"]));
+    assert_close(&floats(&encoded, "colour=red"), &[1.0, 0.0, 1.0, 0.0, 0.0]);
+
+    let restored = encoder.inverse_transform(&encoded).unwrap();
+    assert_eq!(restored.headers, series.headers);
+    assert_eq!(strings(&restored, "colour"), strings(&series, "colour"));
+  }
+
+  #[test]
+  fn transforms_need_fitting() {
+    assert!(matches!(StandardScaler::new(&names(&["x"])).transform(&data()), Err(NNErrors::NotFitted(_))));
+    assert!(matches!(OneHotEncoder::new(&names(&["colour"])).transform(&data()), Err(NNErrors::NotFitted(_))));
+  }
+
+  #[test]
+  fn pipeline_survives_network_serialization() {
+    let series = data();
+    let mut pipeline = Pipeline::new();
+    pipeline.add(StandardScaler::new(&names(&["x"])));
+    pipeline.add(RobustScaler::new(&names(&["n"])));
+    pipeline.add(OneHotEncoder::new(&names(&["colour"])));
+    let expected = pipeline.fit_transform(&series).unwrap().to_array().unwrap();
+
+    for format in [Format::Json, Format::Binary] {
+      let bytes = Network::empty().to_bytes(format, None, Some(&pipeline)).unwrap();
+      let mut restored = Pipeline::new();
+      Network::from_bytes(&bytes, format, None, Some(&mut restored)).unwrap();
+
+      let transformed = restored.transform(&series).unwrap();
+      assert_eq!(transformed.to_array().unwrap(), expected);
+      let back = restored.inverse_transform(&transformed).unwrap();
+      assert_close(&floats(&back, "x"), &floats(&series, "x"));
+      assert_eq!(strings(&back, "colour"), strings(&series, "colour"));
+    }
+  }
+}
@@ -0,0 +1,185 @@
+use ndarray::{Array, Dim};
+use serde::{ Deserialize, Serialize };
+use crate::{errors::NNErrors, layers::Param};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+// Penalty on the weights of a layer: l1 * sum(|w|) + l2 * sum(w^2).
+// Only one of them non zero gives plain L1 or L2, both give elastic net. Biases are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub struct Regularizer {
+  pub l1: f64,
+  pub l2: f64,
+}
+
+impl Regularizer {
+  pub fn l1(l1: f64) -> Result<Regularizer, NNErrors> {
+    Regularizer::elastic_net(l1, 0.0)
+  }
+
+  pub fn l2(l2: f64) -> Result<Regularizer, NNErrors> {
+    Regularizer::elastic_net(0.0, l2)
+  }
+
+  pub fn elastic_net(l1: f64, l2: f64) -> Result<Regularizer, NNErrors> {
+    let regularizer = Regularizer { l1, l2 };
+    regularizer.check()?;
+    Ok(regularizer)
+  }
+
+  // Negative coefficients would reward large weights, NaN poisons every update
+  pub fn check(&self) -> Result<(), NNErrors> {
+    let valid = 0.0..f64::INFINITY;
+    if !valid.contains(&self.l1) || !valid.contains(&self.l2) {
+      return Err(NNErrors::Hyperparameter(format!("regularizer needs finite l1 and l2 of at least 0, got {} and {}", self.l1, self.l2)));
+    }
+    Ok(())
+  }
+
+  pub fn penalty(&self, weights: &Matrix) -> f64 {
+    weights.fold(0.0, |acc, w| acc + self.l1 * w.abs() + self.l2 * w * w)
+  }
+
+  // Gradient of `penalty`, using 0 as the slope of |w| at 0
+  pub fn grad(&self, weights: &Matrix) -> Matrix {
+    weights.mapv(|w| {
+      let sign = if w > 0.0 { 1.0 } else if w < 0.0 { -1.0 } else { 0.0 };
+      self.l1 * sign + 2.0 * self.l2 * w
+    })
+  }
+}
+
+// Bounds the gradients before the optimizer sees them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradClip {
+  // Every gradient entry clamped to [-value, value]
+  Value(f64),
+  // All gradients scaled down together so their global L2 norm is at most this
+  Norm(f64),
+}
+
+impl GradClip {
+  // A negative bound would flip (or make `clamp` panic on) the gradients, NaN would wipe them
+  pub fn check(&self) -> Result<(), NNErrors> {
+    let (GradClip::Value(bound) | GradClip::Norm(bound)) = *self;
+    if !(0.0..=f64::INFINITY).contains(&bound) {
+      return Err(NNErrors::Hyperparameter(format!("{self:?} needs a bound of at least 0")));
+    }
+    Ok(())
+  }
+
+  // Clips in place, returns the global norm of the gradients before and after
+  pub fn apply(&self, params: &mut [Param<'_>]) -> (f64, f64) {
+    let before = global_norm(params);
+
+    match *self {
+      GradClip::Value(value) => params.iter_mut().for_each(|p| p.grad.mapv_inplace(|g| g.clamp(-value, value))),
+      GradClip::Norm(max_norm) if before > max_norm => {
+        let scale = max_norm / before;
+        params.iter_mut().for_each(|p| p.grad.mapv_inplace(|g| g * scale));
+      }
+      GradClip::Norm(_) => {}
+    }
+
+    (before, global_norm(params))
+  }
+}
+
+// L2 norm of all gradients taken as one vector
+pub fn global_norm(params: &[Param<'_>]) -> f64 {
+  params.iter().map(|p| p.grad.fold(0.0, |acc, g| acc + g * g)).sum::<f64>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+  use ndarray::{ArrayD, array};
+  use crate::{layers::Dense, loss::Mse, network::Network, optimizer::Sgd};
+  use super::*;
+
+  fn params(grads: &mut [(ArrayD<f64>, ArrayD<f64>)]) -> Vec<Param<'_>> {
+    grads.iter_mut().map(|(value, grad)| Param { value: value.view_mut(), grad: grad.view_mut() }).collect()
+  }
+
+  fn close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-12, "{a} != {b}");
+  }
+
+  #[test]
+  fn penalty_and_grad() {
+    let weights = array![[1.0, -2.0], [0.0, 0.5]];
+    let regularizer = Regularizer::elastic_net(0.1, 0.2).unwrap();
+    // 0.1 * 3.5 + 0.2 * 5.25
+    close(regularizer.penalty(&weights), 1.4);
+    // The zero weight only gets the L2 part, which is 0 as well
+    let grad = regularizer.grad(&weights);
+    for (g, expected) in grad.iter().zip([0.5, -0.9, 0.0, 0.3]) {
+      close(*g, expected);
+    }
+
+    close(Regularizer::l1(0.1).unwrap().penalty(&weights), 0.35);
+    close(Regularizer::l2(0.2).unwrap().penalty(&weights), 1.05);
+  }
+
+  #[test]
+  fn rejects_bad_coefficients() {
+    assert!(matches!(Regularizer::l1(-0.1), Err(NNErrors::Hyperparameter(_))));
+    assert!(matches!(Regularizer::l2(f64::NAN), Err(NNErrors::Hyperparameter(_))));
+    assert!(matches!(Regularizer::elastic_net(0.1, f64::INFINITY), Err(NNErrors::Hyperparameter(_))));
+
+    let mut nn = Network::empty();
+    nn.add_layer(Dense::new("d", 2, 1));
+    let bad = Some(Regularizer { l1: 0.0, l2: -1.0 });
+    assert!(matches!(nn.regularize("d", bad), Err(NNErrors::Hyperparameter(_))));
+  }
+
+  #[test]
+  fn clip_by_value() {
+    let mut grads = vec![(ArrayD::zeros(vec![2, 2]), array![[3.0, -0.5], [-4.0, 1.0]].into_dyn())];
+    let (before, after) = GradClip::Value(1.0).apply(&mut params(&mut grads));
+    assert_eq!(grads[0].1, array![[1.0, -0.5], [-1.0, 1.0]].into_dyn());
+    close(before, 26.25f64.sqrt());
+    close(after, 3.25f64.sqrt());
+  }
+
+  #[test]
+  fn clip_by_norm() {
+    let mut grads = vec![
+      (ArrayD::zeros(vec![1]), array![3.0].into_dyn()),
+      (ArrayD::zeros(vec![1]), array![4.0].into_dyn()),
+    ];
+    assert_eq!(GradClip::Norm(10.0).apply(&mut params(&mut grads)), (5.0, 5.0));
+    assert_eq!((grads[0].1[0], grads[1].1[0]), (3.0, 4.0));
+
+    let (before, after) = GradClip::Norm(2.5).apply(&mut params(&mut grads));
+    close(before, 5.0);
+    close(after, 2.5);
+    close(grads[0].1[0], 1.5);
+    close(grads[1].1[0], 2.0);
+  }
+
+  #[test]
+  fn train_layer_reports_norms() {
+    let mut nn = Network::empty();
+    let mut dense = Dense::new("d", 2, 2);
+    dense.weights = array![[1.0, -1.0], [0.5, 2.0]];
+    nn.add_layer(dense);
+    let (inputs, targets) = (array![[1.0, 2.0], [-1.0, 0.5]], array![[0.0, 1.0], [1.0, 0.0]]);
+
+    let mut reference = nn.clone();
+    reference.backprop(&Mse::new(), &inputs, &targets, None).unwrap();
+    let norm = global_norm(&reference.params_mut());
+
+    let mut sgd = Sgd::new(0.0);
+    let out = nn.train_layer(&Mse::new(), &inputs, &targets, None, None, &mut sgd).unwrap();
+    close(out.grad_norm, norm);
+    close(out.clipped_norm, norm);
+
+    let out = nn.train_layer(&Mse::new(), &inputs, &targets, None, Some(GradClip::Norm(norm / 2.0)), &mut sgd).unwrap();
+    close(out.grad_norm, norm);
+    close(out.clipped_norm, norm / 2.0);
+
+    for clip in [GradClip::Value(-1.0), GradClip::Norm(-1.0), GradClip::Norm(f64::NAN)] {
+      assert!(matches!(nn.train_layer(&Mse::new(), &inputs, &targets, None, Some(clip), &mut sgd), Err(NNErrors::Hyperparameter(_))));
+    }
+  }
+}
@@ -0,0 +1,317 @@
+use std::f64::consts::PI;
+use std::fmt;
+use crate::optimizer::Optimizer;
+
+// Decides the learning rate step by step, a step being an epoch or a batch depending on
+// how often the training loop calls `step`.
+pub trait LrScheduler: fmt::Debug + Send {
+  // Learning rate for the current step
+  fn lr(&self) -> f64;
+
+  // Moves to the next step. `metric` is the latest validation loss, only schedulers that
+  // react to it (`ReduceOnPlateau`) look at it.
+  fn step(&mut self, metric: Option<f64>);
+
+  fn apply(&self, optimizer: &mut dyn Optimizer) {
+    optimizer.set_learning_rate(self.lr());
+  }
+}
+
+// Multiplies the rate by `gamma` every `step_size` steps
+#[derive(Debug, Clone)]
+pub struct StepDecay {
+  pub base_lr: f64,
+  pub step_size: usize,
+  pub gamma: f64,
+  t: usize,
+}
+
+impl StepDecay {
+  pub fn new(base_lr: f64, step_size: usize, gamma: f64) -> StepDecay {
+    StepDecay { base_lr, step_size: step_size.max(1), gamma, t: 0 }
+  }
+}
+
+impl LrScheduler for StepDecay {
+  fn lr(&self) -> f64 {
+    self.base_lr * self.gamma.powi((self.t / self.step_size) as i32)
+  }
+
+  fn step(&mut self, _metric: Option<f64>) {
+    self.t += 1;
+  }
+}
+
+// Multiplies the rate by `gamma` every step
+#[derive(Debug, Clone)]
+pub struct ExponentialDecay {
+  pub base_lr: f64,
+  pub gamma: f64,
+  t: usize,
+}
+
+impl ExponentialDecay {
+  pub fn new(base_lr: f64, gamma: f64) -> ExponentialDecay {
+    ExponentialDecay { base_lr, gamma, t: 0 }
+  }
+}
+
+impl LrScheduler for ExponentialDecay {
+  fn lr(&self) -> f64 {
+    self.base_lr * self.gamma.powi(self.t as i32)
+  }
+
+  fn step(&mut self, _metric: Option<f64>) {
+    self.t += 1;
+  }
+}
+
+// Cosine annealing from `max_lr` down to `min_lr` with warm restarts (SGDR).
+// The first cycle lasts `period` steps, every next one is `period_mult` times longer.
+#[derive(Debug, Clone)]
+pub struct CosineAnnealing {
+  pub max_lr: f64,
+  pub min_lr: f64,
+  pub period: usize,
+  pub period_mult: usize,
+  // Position inside the current cycle and that cycle's length
+  t: usize,
+  cycle_len: usize,
+}
+
+impl CosineAnnealing {
+  pub fn new(max_lr: f64, min_lr: f64, period: usize, period_mult: usize) -> CosineAnnealing {
+    let period = period.max(1);
+    CosineAnnealing { max_lr, min_lr, period, period_mult: period_mult.max(1), t: 0, cycle_len: period }
+  }
+}
+
+impl LrScheduler for CosineAnnealing {
+  fn lr(&self) -> f64 {
+    let progress = self.t as f64 / self.cycle_len as f64;
+    self.min_lr + 0.5 * (self.max_lr - self.min_lr) * (1.0 + (PI * progress).cos())
+  }
+
+  fn step(&mut self, _metric: Option<f64>) {
+    self.t += 1;
+    if self.t >= self.cycle_len {
+      self.t = 0;
+      self.cycle_len *= self.period_mult;
+    }
+  }
+}
+
+// Ramps the rate linearly up to the one of `after` over `steps` steps, then hands over to it
+#[derive(Debug)]
+pub struct LinearWarmup {
+  pub steps: usize,
+  pub after: Box<dyn LrScheduler>,
+  t: usize,
+}
+
+impl LinearWarmup {
+  pub fn new(steps: usize, after: Box<dyn LrScheduler>) -> LinearWarmup {
+    LinearWarmup { steps, after, t: 0 }
+  }
+}
+
+impl LrScheduler for LinearWarmup {
+  fn lr(&self) -> f64 {
+    if self.t < self.steps {
+      self.after.lr() * (self.t + 1) as f64 / self.steps as f64
+    } else {
+      self.after.lr()
+    }
+  }
+
+  fn step(&mut self, metric: Option<f64>) {
+    if self.t < self.steps {
+      self.t += 1;
+    } else {
+      self.after.step(metric);
+    }
+  }
+}
+
+// One-cycle policy: cosine ramp from max_lr / div_factor up to `max_lr` during the first
+// `pct_start` of `total_steps`, then cosine decay down to max_lr / (div_factor * final_div_factor)
+#[derive(Debug, Clone)]
+pub struct OneCycle {
+  pub max_lr: f64,
+  pub total_steps: usize,
+  pub pct_start: f64,
+  pub div_factor: f64,
+  pub final_div_factor: f64,
+  t: usize,
+}
+
+impl OneCycle {
+  pub fn new(max_lr: f64, total_steps: usize) -> OneCycle {
+    OneCycle { max_lr, total_steps, pct_start: 0.3, div_factor: 25.0, final_div_factor: 1e4, t: 0 }
+  }
+}
+
+impl LrScheduler for OneCycle {
+  fn lr(&self) -> f64 {
+    let anneal = |from: f64, to: f64, progress: f64| to + 0.5 * (from - to) * (1.0 + (PI * progress).cos());
+
+    let initial_lr = self.max_lr / self.div_factor;
+    let final_lr = initial_lr / self.final_div_factor;
+    let warm_steps = ((self.total_steps as f64 * self.pct_start) as usize).max(1);
+    let t = self.t.min(self.total_steps);
+
+    if t < warm_steps {
+      anneal(initial_lr, self.max_lr, t as f64 / warm_steps as f64)
+    } else {
+      let decay_steps = self.total_steps.saturating_sub(warm_steps).max(1);
+      anneal(self.max_lr, final_lr, (t - warm_steps) as f64 / decay_steps as f64)
+    }
+  }
+
+  fn step(&mut self, _metric: Option<f64>) {
+    self.t += 1;
+  }
+}
+
+// Multiplies the rate by `factor` once the metric has gone more than `patience` steps in a row
+// without improving by at least `threshold`, never going below `min_lr`. For `cooldown` steps
+// after a reduction the metric is given time to react and bad steps are not counted.
+#[derive(Debug, Clone)]
+pub struct ReduceOnPlateau {
+  pub factor: f64,
+  pub patience: usize,
+  pub threshold: f64,
+  pub min_lr: f64,
+  pub cooldown: usize,
+  lr: f64,
+  best: f64,
+  bad_steps: usize,
+  cooldown_left: usize,
+}
+
+impl ReduceOnPlateau {
+  pub fn new(lr: f64, factor: f64, patience: usize) -> ReduceOnPlateau {
+    ReduceOnPlateau {
+      factor,
+      patience,
+      threshold: 1e-4,
+      min_lr: 0.0,
+      cooldown: 0,
+      lr,
+      best: f64::INFINITY,
+      bad_steps: 0,
+      cooldown_left: 0,
+    }
+  }
+}
+
+impl LrScheduler for ReduceOnPlateau {
+  fn lr(&self) -> f64 {
+    self.lr
+  }
+
+  fn step(&mut self, metric: Option<f64>) {
+    let metric = match metric {
+      Some(metric) => metric,
+      None => return,
+    };
+
+    if metric < self.best - self.threshold {
+      self.best = metric;
+      self.bad_steps = 0;
+    } else {
+      self.bad_steps += 1;
+    }
+
+    if self.cooldown_left > 0 {
+      self.cooldown_left -= 1;
+      self.bad_steps = 0;
+    }
+
+    if self.bad_steps > self.patience {
+      self.lr = (self.lr * self.factor).max(self.min_lr);
+      self.bad_steps = 0;
+      self.cooldown_left = self.cooldown;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-12, "{a} != {b}");
+  }
+
+  // Rate after every one of `n` steps, starting with the one before the first step
+  fn rates(scheduler: &mut dyn LrScheduler, n: usize) -> Vec<f64> {
+    let mut out = vec![scheduler.lr()];
+    for _ in 0..n {
+      scheduler.step(None);
+      out.push(scheduler.lr());
+    }
+    out
+  }
+
+  #[test]
+  fn step_and_exponential_decay() {
+    let lrs = rates(&mut StepDecay::new(0.1, 3, 0.5), 7);
+    for (epoch, expected) in [(0, 0.1), (2, 0.1), (3, 0.05), (5, 0.05), (6, 0.025), (7, 0.025)] {
+      close(lrs[epoch], expected);
+    }
+
+    let lrs = rates(&mut ExponentialDecay::new(0.1, 0.9), 3);
+    close(lrs[1], 0.09);
+    close(lrs[3], 0.0729);
+  }
+
+  #[test]
+  fn cosine_annealing_restarts() {
+    // Cycles of 4, 8 and 16 steps, restarting at steps 4 and 12
+    let lrs = rates(&mut CosineAnnealing::new(1.0, 0.1, 4, 2), 12);
+    close(lrs[0], 1.0);
+    close(lrs[2], 0.55);
+    close(lrs[3], 0.1 + 0.45 * (1.0 - 0.5f64.sqrt()));
+    close(lrs[4], 1.0);
+    close(lrs[8], 0.55);
+    close(lrs[11], 0.1 + 0.45 * (1.0 + (PI * 7.0 / 8.0).cos()));
+    close(lrs[12], 1.0);
+  }
+
+  #[test]
+  fn warmup_hands_over_to_its_schedule() {
+    let lrs = rates(&mut LinearWarmup::new(3, Box::new(StepDecay::new(0.3, 2, 0.5))), 6);
+    // Ramp to 0.3, then the decay starts from its own first step
+    for (step, expected) in [0.1, 0.2, 0.3, 0.3, 0.3, 0.15, 0.15].into_iter().enumerate() {
+      close(lrs[step], expected);
+    }
+  }
+
+  #[test]
+  fn one_cycle_start_peak_and_end() {
+    // 3 warm-up steps from 1 / 25 to 1, then 7 down to 1 / 25e4
+    let lrs = rates(&mut OneCycle::new(1.0, 10), 12);
+    close(lrs[0], 0.04);
+    close(lrs[3], 1.0);
+    assert!(lrs[1] < lrs[2] && lrs[2] < lrs[3] && lrs[3] > lrs[4]);
+    close(lrs[10], 4e-6);
+    close(lrs[12], 4e-6);
+  }
+
+  #[test]
+  fn reduce_on_plateau() {
+    let mut scheduler = ReduceOnPlateau { min_lr: 0.2, cooldown: 1, ..ReduceOnPlateau::new(1.0, 0.5, 2) };
+    let metrics = [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.5];
+    // Three bad steps in a row past patience halve the rate, the step after a reduction does not count
+    let expected = [1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.5, 0.25, 0.25, 0.25, 0.25, 0.2];
+    for (metric, lr) in metrics.into_iter().zip(expected) {
+      scheduler.step(Some(metric));
+      close(scheduler.lr(), lr);
+    }
+
+    // Steps without a metric change nothing
+    scheduler.step(None);
+    close(scheduler.lr(), 0.2);
+  }
+}
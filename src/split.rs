@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use ndarray::{Array, Axis, Dim};
+use rand::{RngCore, seq::SliceRandom};
+use crate::{
+  data_processing::Series,
+  errors::NNErrors,
+  preludes::argmax,
+};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+// Row indices of every part of a split, each part shuffled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Split {
+  pub train: Vec<usize>,
+  pub val: Vec<usize>,
+  pub test: Vec<usize>,
+}
+
+impl Split {
+  // Plain random split, `val_ratio` and `test_ratio` of the rows go to validation and test
+  pub fn random(n_rows: usize, val_ratio: f64, test_ratio: f64, rng: &mut dyn RngCore) -> Result<Split, NNErrors> {
+    Split::stratified(&vec![0; n_rows], val_ratio, test_ratio, rng)
+  }
+
+  // Every class of `labels` is split on its own, so all parts keep the class proportions
+  pub fn stratified(labels: &[usize], val_ratio: f64, test_ratio: f64, rng: &mut dyn RngCore) -> Result<Split, NNErrors> {
+    check_ratios(val_ratio, test_ratio)?;
+
+    let mut split = Split::default();
+    for mut rows in by_key(labels) {
+      rows.shuffle(rng);
+      let n_test = (rows.len() as f64 * test_ratio).round() as usize;
+      let n_val = ((rows.len() as f64 * val_ratio).round() as usize).min(rows.len() - n_test);
+
+      split.test.extend_from_slice(&rows[..n_test]);
+      split.val.extend_from_slice(&rows[n_test..n_test + n_val]);
+      split.train.extend_from_slice(&rows[n_test + n_val..]);
+    }
+
+    split.shuffle(rng);
+    Ok(split)
+  }
+
+  // Rows sharing a group (patient, session, ...) all end up in the same part, so nothing
+  // leaks between them. Parts get close to their ratio, not exactly it.
+  pub fn grouped(groups: &[usize], val_ratio: f64, test_ratio: f64, rng: &mut dyn RngCore) -> Result<Split, NNErrors> {
+    check_ratios(val_ratio, test_ratio)?;
+
+    let n_test = (groups.len() as f64 * test_ratio).round() as usize;
+    let n_val = (groups.len() as f64 * val_ratio).round() as usize;
+
+    let mut split = Split::default();
+    let mut keys = by_key(groups);
+    keys.shuffle(rng);
+    for rows in keys {
+      let part = if split.test.len() < n_test {
+        &mut split.test
+      } else if split.val.len() < n_val {
+        &mut split.val
+      } else {
+        &mut split.train
+      };
+      part.extend(rows);
+    }
+
+    split.shuffle(rng);
+    Ok(split)
+  }
+
+  // Training, validation and test series, in that order
+  pub fn series(&self, series: &Series) -> (Series, Series, Series) {
+    (series.select_rows(&self.train), series.select_rows(&self.val), series.select_rows(&self.test))
+  }
+
+  // Training, validation and test rows of `array`, in that order
+  pub fn arrays(&self, array: &Matrix) -> (Matrix, Matrix, Matrix) {
+    (rows(array, &self.train), rows(array, &self.val), rows(array, &self.test))
+  }
+
+  fn shuffle(&mut self, rng: &mut dyn RngCore) {
+    self.train.shuffle(rng);
+    self.val.shuffle(rng);
+    self.test.shuffle(rng);
+  }
+}
+
+// One round of cross-validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fold {
+  pub train: Vec<usize>,
+  pub val: Vec<usize>,
+}
+
+impl Fold {
+  pub fn series(&self, series: &Series) -> (Series, Series) {
+    (series.select_rows(&self.train), series.select_rows(&self.val))
+  }
+
+  pub fn arrays(&self, array: &Matrix) -> (Matrix, Matrix) {
+    (rows(array, &self.train), rows(array, &self.val))
+  }
+}
+
+// Splits the rows into `k` folds and yields `k` rounds, each one validating on another fold
+// and training on the rest
+#[derive(Debug, Clone)]
+pub struct KFold {
+  folds: Vec<Vec<usize>>,
+  next: usize,
+}
+
+impl KFold {
+  pub fn new(n_rows: usize, k: usize, rng: &mut dyn RngCore) -> Result<KFold, NNErrors> {
+    KFold::stratified(&vec![0; n_rows], k, rng)
+  }
+
+  // Rows of every class are dealt over the folds in turn, so every fold keeps the class proportions
+  pub fn stratified(labels: &[usize], k: usize, rng: &mut dyn RngCore) -> Result<KFold, NNErrors> {
+    check_folds(labels.len(), k)?;
+
+    let mut folds = vec![Vec::new(); k];
+    let mut fold = 0;
+    for mut rows in by_key(labels) {
+      rows.shuffle(rng);
+      for row in rows {
+        folds[fold].push(row);
+        fold = (fold + 1) % k;
+      }
+    }
+
+    Ok(KFold::from_folds(folds, rng))
+  }
+
+  // Whole groups go to one fold, biggest groups first onto the fold with the fewest rows
+  pub fn grouped(groups: &[usize], k: usize, rng: &mut dyn RngCore) -> Result<KFold, NNErrors> {
+    let mut keys = by_key(groups);
+    check_folds(keys.len(), k)?;
+
+    keys.shuffle(rng);
+    keys.sort_by_key(|rows| std::cmp::Reverse(rows.len()));
+
+    let mut folds: Vec<Vec<usize>> = vec![Vec::new(); k];
+    for rows in keys {
+      if let Some(fold) = folds.iter_mut().min_by_key(|fold| fold.len()) {
+        fold.extend(rows);
+      }
+    }
+
+    Ok(KFold::from_folds(folds, rng))
+  }
+
+  pub fn k(&self) -> usize {
+    self.folds.len()
+  }
+
+  fn from_folds(mut folds: Vec<Vec<usize>>, rng: &mut dyn RngCore) -> KFold {
+    folds.iter_mut().for_each(|fold| fold.shuffle(rng));
+    KFold { folds, next: 0 }
+  }
+}
+
+impl Iterator for KFold {
+  type Item = Fold;
+
+  fn next(&mut self) -> Option<Fold> {
+    let val = self.folds.get(self.next)?.clone();
+    let train = self.folds.iter().enumerate()
+      .filter(|(i, _)| *i != self.next)
+      .flat_map(|(_, fold)| fold.iter().copied())
+      .collect();
+
+    self.next += 1;
+    Some(Fold { train, val })
+  }
+}
+
+// Index of every row's value among `unique_in_col`, to stratify or group on a column.
+// Missing values form a class of their own.
+pub fn strata(series: &Series, col_name: &str) -> Result<Vec<usize>, NNErrors> {
+  let unique = series.unique_in_col(col_name)?;
+  let col = series.col(col_name)?;
+  Ok((0..col.len()).map(|row| match col.get(row) {
+    Some(value) => unique.iter().position(|u| *u == value).unwrap_or(unique.len()),
+    None => unique.len(),
+  }).collect())
+}
+
+// Class of every row of one-hot (or score) targets
+pub fn class_labels(targets: &Matrix) -> Vec<usize> {
+  targets.rows().into_iter().map(|row| argmax(&row.to_vec()).0).collect()
+}
+
+pub fn rows(array: &Matrix, idx: &[usize]) -> Matrix {
+  array.select(Axis(0), idx)
+}
+
+// Rows of every distinct key, in order of first appearance
+fn by_key(keys: &[usize]) -> Vec<Vec<usize>> {
+  let mut position = HashMap::new();
+  let mut out: Vec<Vec<usize>> = Vec::new();
+  for (row, key) in keys.iter().enumerate() {
+    let i = *position.entry(*key).or_insert_with(|| {
+      out.push(Vec::new());
+      out.len() - 1
+    });
+    out[i].push(row);
+  }
+  out
+}
+
+fn check_ratios(val_ratio: f64, test_ratio: f64) -> Result<(), NNErrors> {
+  if !(0.0..1.0).contains(&val_ratio) || !(0.0..1.0).contains(&test_ratio) || val_ratio + test_ratio >= 1.0 {
+    return Err(NNErrors::BadSplit(format!("ratios {val_ratio} and {test_ratio} leave no training rows")));
+  }
+  Ok(())
+}
+
+fn check_folds(n: usize, k: usize) -> Result<(), NNErrors> {
+  if k < 2 || k > n {
+    return Err(NNErrors::BadSplit(format!("can not make {k} folds out of {n}")));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, rngs::StdRng};
+  use super::*;
+
+  // 60 rows of classes 0, 1 and 2 in proportions 1:2:3
+  fn labels() -> Vec<usize> {
+    (0..60).map(|i| match i % 6 { 0 => 0, 1 | 2 => 1, _ => 2 }).collect()
+  }
+
+  // 40 rows in 13 groups of uneven size
+  fn groups() -> Vec<usize> {
+    (0..40).map(|i| (i * i) % 13).collect()
+  }
+
+  fn assert_partition(parts: &[&[usize]], n_rows: usize) {
+    let mut all: Vec<usize> = parts.iter().flat_map(|part| part.iter().copied()).collect();
+    all.sort_unstable();
+    assert_eq!(all, (0..n_rows).collect::<Vec<_>>());
+  }
+
+  fn count(labels: &[usize], rows: &[usize], class: usize) -> usize {
+    rows.iter().filter(|row| labels[**row] == class).count()
+  }
+
+  fn assert_groups_kept(groups: &[usize], parts: &[&[usize]]) {
+    for (i, a) in parts.iter().enumerate() {
+      for b in &parts[i + 1..] {
+        assert!(a.iter().all(|x| b.iter().all(|y| groups[*x] != groups[*y])));
+      }
+    }
+  }
+
+  #[test]
+  fn random_split_partitions_rows() {
+    let split = Split::random(50, 0.2, 0.1, &mut StdRng::seed_from_u64(1)).unwrap();
+    assert_eq!((split.train.len(), split.val.len(), split.test.len()), (35, 10, 5));
+    assert_partition(&[&split.train, &split.val, &split.test], 50);
+  }
+
+  #[test]
+  fn stratified_split_keeps_proportions() {
+    let labels = labels();
+    let split = Split::stratified(&labels, 0.2, 0.1, &mut StdRng::seed_from_u64(2)).unwrap();
+    assert_partition(&[&split.train, &split.val, &split.test], 60);
+    for (part, expected) in [(&split.train, [7, 14, 21]), (&split.val, [2, 4, 6]), (&split.test, [1, 2, 3])] {
+      let counts: Vec<usize> = (0..3).map(|class| count(&labels, part, class)).collect();
+      assert_eq!(counts, expected);
+    }
+  }
+
+  #[test]
+  fn grouped_split_never_splits_a_group() {
+    let groups = groups();
+    for seed in 0..5 {
+      let split = Split::grouped(&groups, 0.2, 0.2, &mut StdRng::seed_from_u64(seed)).unwrap();
+      assert_partition(&[&split.train, &split.val, &split.test], 40);
+      assert_groups_kept(&groups, &[&split.train, &split.val, &split.test]);
+    }
+  }
+
+  #[test]
+  fn k_folds_partition_rows() {
+    let labels = labels();
+    let folds: Vec<Fold> = KFold::stratified(&labels, 3, &mut StdRng::seed_from_u64(3)).unwrap().collect();
+    assert_eq!(folds.len(), 3);
+    let vals: Vec<&[usize]> = folds.iter().map(|fold| &fold.val[..]).collect();
+    assert_partition(&vals, 60);
+    for fold in &folds {
+      assert_partition(&[&fold.train, &fold.val], 60);
+      let counts: Vec<usize> = (0..3).map(|class| count(&labels, &fold.val, class)).collect();
+      assert!(counts.iter().zip([10.0 / 3.0, 20.0 / 3.0, 10.0]).all(|(c, e)| (*c as f64 - e).abs() < 1.0));
+    }
+  }
+
+  #[test]
+  fn grouped_k_folds_never_split_a_group() {
+    let groups = groups();
+    for seed in 0..5 {
+      let kfold = KFold::grouped(&groups, 4, &mut StdRng::seed_from_u64(seed)).unwrap();
+      assert_eq!(kfold.k(), 4);
+      for fold in kfold {
+        assert_partition(&[&fold.train, &fold.val], 40);
+        assert_groups_kept(&groups, &[&fold.train, &fold.val]);
+      }
+    }
+  }
+
+  #[test]
+  fn same_seed_gives_same_split() {
+    let split = |seed| Split::stratified(&labels(), 0.2, 0.2, &mut StdRng::seed_from_u64(seed)).unwrap();
+    assert_eq!(split(7), split(7));
+    assert_ne!(split(7), split(8));
+
+    let folds = |seed| KFold::grouped(&groups(), 3, &mut StdRng::seed_from_u64(seed)).unwrap().collect::<Vec<_>>();
+    assert_eq!(folds(7), folds(7));
+  }
+
+  #[test]
+  fn rejects_bad_ratios_and_fold_counts() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    assert!(matches!(Split::random(10, 0.5, 0.5, rng), Err(NNErrors::BadSplit(_))));
+    assert!(matches!(Split::random(10, -0.1, 0.2, rng), Err(NNErrors::BadSplit(_))));
+    assert!(matches!(KFold::new(10, 1, rng), Err(NNErrors::BadSplit(_))));
+    assert!(matches!(KFold::grouped(&[0, 0, 1], 3, rng), Err(NNErrors::BadSplit(_))));
+  }
+}
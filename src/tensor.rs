@@ -0,0 +1,51 @@
+use ndarray::{Array, ArrayD, Dim, Dimension, IxDyn};
+use crate::errors::NNErrors;
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+
+// What flows between layers: a batch of samples along the first axis, every sample
+// of any shape, e.g. [features], [steps, features] or [channels, height, width]
+pub type Tensor = ArrayD<f64>;
+
+pub fn to_tensor<D: Dimension>(array: &Array<f64, D>) -> Tensor {
+  array.view().into_dyn().to_owned()
+}
+
+// Shape of one sample: everything after the batch axis
+pub fn sample_shape(tensor: &Tensor) -> &[usize] {
+  tensor.shape().get(1..).unwrap_or(&[])
+}
+
+pub fn batch_size(tensor: &Tensor) -> usize {
+  tensor.shape().first().copied().unwrap_or(0)
+}
+
+// Every sample flattened into one row: (batch, values per sample)
+pub fn to_rows(tensor: &Tensor) -> Result<Matrix, NNErrors> {
+  to_matrix(tensor, batch_size(tensor), sample_shape(tensor).iter().product())
+}
+
+// Every position along all but the last axis as one row: (values / last, last)
+pub fn last_axis_rows(tensor: &Tensor) -> Result<Matrix, NNErrors> {
+  let last = tensor.shape().last().copied().unwrap_or(1).max(1);
+  to_matrix(tensor, tensor.len() / last, last)
+}
+
+// Same values read with another shape of the same size, in row-major order
+pub fn reshape<D: Dimension>(array: &Array<f64, D>, shape: &[usize]) -> Result<Tensor, NNErrors> {
+  array.to_shape(IxDyn(shape))
+    .map(|view| view.into_owned())
+    .map_err(|_| NNErrors::ShapeMismatch(array.shape().to_vec(), shape.to_vec()))
+}
+
+// The values of `tensor` as a (rows, cols) matrix
+pub(crate) fn to_matrix(tensor: &Tensor, rows: usize, cols: usize) -> Result<Matrix, NNErrors> {
+  tensor.to_shape((rows, cols))
+    .map(|view| view.into_owned())
+    .map_err(|_| NNErrors::ShapeMismatch(tensor.shape().to_vec(), vec![rows, cols]))
+}
+
+// `shape` of one sample behind a batch of `batch`
+pub fn batch_shape(batch: usize, shape: &[usize]) -> Vec<usize> {
+  std::iter::once(batch).chain(shape.iter().copied()).collect()
+}
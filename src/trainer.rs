@@ -0,0 +1,413 @@
+use std::fmt;
+use ndarray::{s, Array, Axis, Dim, Dimension, Slice};
+use rand::{RngCore, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use crate::{
+  errors::NNErrors,
+  loss::Loss,
+  network::{Format, Network},
+  optimizer::Optimizer,
+  regularization::GradClip,
+  scheduler::LrScheduler,
+  tensor,
+};
+
+type Matrix = Array<f64, Dim<[usize; 2]>>;
+type Vector = Array<f64, Dim<[usize; 1]>>;
+
+#[derive(Debug, Clone)]
+pub struct EpochMetrics {
+  pub epoch: usize,
+  // Mean loss over the training batches
+  pub loss: f64,
+  pub val_loss: Option<f64>,
+  pub lr: f64,
+}
+
+impl EpochMetrics {
+  // What callbacks and schedulers watch: the validation loss when there is one
+  pub fn monitored(&self) -> f64 {
+    self.val_loss.unwrap_or(self.loss)
+  }
+}
+
+pub enum Control {
+  Continue,
+  Stop,
+}
+
+pub trait Callback: fmt::Debug {
+  fn on_epoch_end(&mut self, nn: &Network, metrics: &EpochMetrics) -> Result<Control, NNErrors>;
+
+  fn on_train_end(&mut self, _nn: &mut Network) {}
+}
+
+// Runs epochs of shuffled mini-batches over a dataset, holding out the last `validation_split`
+// of the rows for validation
+pub struct Trainer<'a> {
+  pub network: Network,
+  pub loss: Box<dyn Loss + 'a>,
+  pub optimizer: Box<dyn Optimizer + 'a>,
+  // Stepped once per epoch with the monitored loss
+  pub scheduler: Option<Box<dyn LrScheduler + 'a>>,
+  pub callbacks: Vec<Box<dyn Callback + 'a>>,
+  // Applied to the gradients of every batch before the optimizer step
+  pub clip: Option<GradClip>,
+  pub epochs: usize,
+  pub batch_size: usize,
+  pub validation_split: f64,
+  pub shuffle: bool,
+  rng: StdRng,
+}
+
+impl<'a> Trainer<'a> {
+  // The batch order is drawn from a generator seeded from `rng`, so a seeded `rng` repeats the run
+  pub fn new(
+    network: Network,
+    loss: Box<dyn Loss + 'a>,
+    optimizer: Box<dyn Optimizer + 'a>,
+    rng: &mut dyn RngCore,
+  ) -> Trainer<'a> {
+    Trainer {
+      network,
+      loss,
+      optimizer,
+      scheduler: None,
+      callbacks: Vec::new(),
+      clip: None,
+      epochs: 10,
+      batch_size: 32,
+      validation_split: 0.0,
+      shuffle: true,
+      rng: StdRng::seed_from_u64(rng.next_u64()),
+    }
+  }
+
+  // Reseeds the generator behind the batch order
+  pub fn seed(&mut self, seed: u64) {
+    self.rng = StdRng::seed_from_u64(seed);
+  }
+
+  pub fn add_callback<C: Callback + 'a>(&mut self, callback: C) {
+    self.callbacks.push(Box::new(callback));
+  }
+
+  // `inputs` holds one sample per entry of its first axis, samples may have any shape the first layer takes
+  pub fn fit<D: Dimension>(&mut self, inputs: &Array<f64, D>, targets: &Matrix) -> Result<Vec<EpochMetrics>, NNErrors> {
+    self.fit_weighted(inputs, targets, None)
+  }
+
+  // Like `fit`, every row's loss multiplied by its weight in `sample_weights`
+  pub fn fit_weighted<D: Dimension>(
+    &mut self,
+    inputs: &Array<f64, D>,
+    targets: &Matrix,
+    sample_weights: Option<&Vector>,
+  ) -> Result<Vec<EpochMetrics>, NNErrors> {
+    let inputs = tensor::to_tensor(inputs);
+    let n_rows = tensor::batch_size(&inputs);
+    if n_rows != targets.nrows() {
+      return Err(NNErrors::RowMismatch(n_rows, targets.nrows()));
+    }
+    if let Some(weights) = sample_weights.filter(|w| w.len() != n_rows) {
+      return Err(NNErrors::RowMismatch(n_rows, weights.len()));
+    }
+
+    let n_val = ((n_rows as f64 * self.validation_split).round() as usize).min(n_rows);
+    let n_train = n_rows - n_val;
+    let train_x = inputs.slice_axis(Axis(0), Slice::from(..n_train));
+    let val_x = inputs.slice_axis(Axis(0), Slice::from(n_train..));
+    let (train_y, val_y) = (targets.slice(s![..n_train, ..]), targets.slice(s![n_train.., ..]));
+    let (train_w, val_w) = match sample_weights {
+      Some(weights) => (Some(weights.slice(s![..n_train])), Some(weights.slice(s![n_train..]).to_owned())),
+      None => (None, None),
+    };
+
+    let mut order: Vec<usize> = (0..n_train).collect();
+    let mut history = Vec::with_capacity(self.epochs);
+
+    for epoch in 0..self.epochs {
+      if let Some(scheduler) = &self.scheduler {
+        scheduler.apply(self.optimizer.as_mut());
+      }
+      if self.shuffle {
+        order.shuffle(&mut self.rng);
+      }
+
+      let mut loss_sum = 0.0;
+      for batch in order.chunks(self.batch_size.max(1)) {
+        let batch_x = train_x.select(Axis(0), batch);
+        let batch_y = train_y.select(Axis(0), batch);
+
+        let batch_w = train_w.map(|w| w.select(Axis(0), batch));
+
+        let out = self.network.train_layer(
+          self.loss.as_ref(), &batch_x, &batch_y, batch_w.as_ref(), self.clip, self.optimizer.as_mut(),
+        )?;
+        loss_sum += out.error * batch.len() as f64;
+      }
+
+      let val_loss = match n_val {
+        0 => None,
+        _ => Some(self.loss.value(&self.network.output(&val_x.to_owned())?, &val_y.to_owned(), val_w.as_ref())),
+      };
+      let metrics = EpochMetrics {
+        epoch,
+        loss: loss_sum / n_train.max(1) as f64,
+        val_loss,
+        lr: self.optimizer.learning_rate(),
+      };
+
+      if let Some(scheduler) = self.scheduler.as_mut() {
+        scheduler.step(Some(metrics.monitored()));
+      }
+
+      let mut stop = false;
+      for callback in self.callbacks.iter_mut() {
+        if let Control::Stop = callback.on_epoch_end(&self.network, &metrics)? {
+          stop = true;
+        }
+      }
+
+      history.push(metrics);
+      if stop {
+        break;
+      }
+    }
+
+    for callback in self.callbacks.iter_mut() {
+      callback.on_train_end(&mut self.network);
+    }
+
+    Ok(history)
+  }
+}
+
+// Stops once the monitored loss has not improved by `min_delta` for `patience` epochs,
+// optionally rolling the network back to its best epoch
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+  pub patience: usize,
+  pub min_delta: f64,
+  pub restore_best: bool,
+  best: f64,
+  best_network: Option<Network>,
+  wait: usize,
+}
+
+impl EarlyStopping {
+  pub fn new(patience: usize) -> EarlyStopping {
+    EarlyStopping { patience, min_delta: 0.0, restore_best: true, best: f64::INFINITY, best_network: None, wait: 0 }
+  }
+}
+
+impl Callback for EarlyStopping {
+  fn on_epoch_end(&mut self, nn: &Network, metrics: &EpochMetrics) -> Result<Control, NNErrors> {
+    if metrics.monitored() < self.best - self.min_delta {
+      self.best = metrics.monitored();
+      self.wait = 0;
+      if self.restore_best {
+        self.best_network = Some(nn.clone());
+      }
+      return Ok(Control::Continue);
+    }
+
+    self.wait += 1;
+    Ok(if self.wait >= self.patience { Control::Stop } else { Control::Continue })
+  }
+
+  fn on_train_end(&mut self, nn: &mut Network) {
+    if let Some(best) = self.best_network.take() {
+      *nn = best;
+    }
+  }
+}
+
+// Saves the network to `path` after every epoch, or only when the monitored loss improves
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+  pub path: String,
+  pub format: Format,
+  pub best_only: bool,
+  best: f64,
+}
+
+impl Checkpoint {
+  pub fn new(path: &str, format: Format, best_only: bool) -> Checkpoint {
+    Checkpoint { path: path.to_string(), format, best_only, best: f64::INFINITY }
+  }
+}
+
+impl Callback for Checkpoint {
+  fn on_epoch_end(&mut self, nn: &Network, metrics: &EpochMetrics) -> Result<Control, NNErrors> {
+    if self.best_only && metrics.monitored() >= self.best {
+      return Ok(Control::Continue);
+    }
+    self.best = self.best.min(metrics.monitored());
+
+    nn.save(&self.path, self.format, None, None)?;
+    Ok(Control::Continue)
+  }
+}
+
+// Prints the metrics every `every` epochs
+#[derive(Debug, Clone)]
+pub struct Logger {
+  pub every: usize,
+}
+
+impl Logger {
+  pub fn new(every: usize) -> Logger {
+    Logger { every: every.max(1) }
+  }
+}
+
+impl Callback for Logger {
+  fn on_epoch_end(&mut self, _nn: &Network, metrics: &EpochMetrics) -> Result<Control, NNErrors> {
+    if metrics.epoch.is_multiple_of(self.every) {
+      match metrics.val_loss {
+        Some(val_loss) => println!(
+          "epoch {}: loss {:.5}, val_loss {:.5}, lr {:.5}", metrics.epoch, metrics.loss, val_loss, metrics.lr
+        ),
+        None => println!("epoch {}: loss {:.5}, lr {:.5}", metrics.epoch, metrics.loss, metrics.lr),
+      }
+    }
+    Ok(Control::Continue)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{Rng, SeedableRng, rngs::StdRng};
+  use crate::{
+    activation::ActivationType,
+    initializer::Initializer,
+    layers::{Dense, Dropout},
+    loss::{Loss, Mse},
+    optimizer::Sgd,
+  };
+  use super::*;
+
+  // Builds, initializes and trains a network with dropout from nothing but `seed`
+  fn train(seed: u64) -> Vec<(String, usize, f64)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let inputs = Array::from_shape_fn((40, 3), |_| rng.gen_range(-1.0..1.0));
+    let targets = Array::from_shape_fn((40, 2), |_| rng.gen_range(0.0..1.0));
+
+    let mut nn = Network::new(vec![("hidden", 3, 8, ActivationType::ReLU)], &mut rng);
+    nn.add_layer(Dropout::new("dropout", 0.3).unwrap());
+    nn.add_layer(Dense::new("out", 8, 2));
+    nn.init_layer("out", &Initializer::GlorotUniform, &mut rng).unwrap();
+
+    let mut trainer = Trainer::new(nn, Box::new(Mse::new()), Box::new(Sgd::new(0.1)), &mut rng);
+    trainer.epochs = 3;
+    trainer.batch_size = 8;
+    trainer.fit(&inputs, &targets).unwrap();
+    trainer.network.weights_to_vec()
+  }
+
+  #[test]
+  fn same_seed_gives_identical_weights() {
+    assert_eq!(train(3), train(3));
+    assert_ne!(train(3), train(4));
+  }
+
+  // Keeps the network and metrics of every epoch
+  #[derive(Debug)]
+  struct Recorder<'b>(&'b mut Vec<(Network, EpochMetrics)>);
+
+  impl Callback for Recorder<'_> {
+    fn on_epoch_end(&mut self, nn: &Network, metrics: &EpochMetrics) -> Result<Control, NNErrors> {
+      self.0.push((nn.clone(), metrics.clone()));
+      Ok(Control::Continue)
+    }
+  }
+
+  fn data(seed: u64) -> (Network, Matrix, Matrix) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let inputs = Array::from_shape_fn((8, 3), |_| rng.gen_range(-1.0..1.0));
+    let targets = Array::from_shape_fn((8, 2), |_| rng.gen_range(-1.0..1.0));
+    let nn = Network::new(vec![("hidden", 3, 4, ActivationType::Tanh), ("out", 4, 2, ActivationType::Linear)], &mut rng);
+    (nn, inputs, targets)
+  }
+
+  fn metrics(epoch: usize, loss: f64) -> EpochMetrics {
+    EpochMetrics { epoch, loss, val_loss: None, lr: 0.1 }
+  }
+
+  #[test]
+  fn early_stopping_waits_patience_epochs_and_restores_the_best() {
+    let networks: Vec<Network> = (0..5).map(|seed| data(seed).0).collect();
+    let mut early = EarlyStopping::new(3);
+    for (epoch, loss) in [3.0, 2.0, 2.5, 2.1].into_iter().enumerate() {
+      assert!(matches!(early.on_epoch_end(&networks[epoch], &metrics(epoch, loss)).unwrap(), Control::Continue));
+    }
+    assert!(matches!(early.on_epoch_end(&networks[4], &metrics(4, 2.2)).unwrap(), Control::Stop));
+
+    let mut nn = networks[4].clone();
+    early.on_train_end(&mut nn);
+    assert_eq!(nn.weights_to_vec(), networks[1].weights_to_vec());
+  }
+
+  #[test]
+  fn trainer_stops_early_on_a_rising_loss() {
+    let (nn, inputs, targets) = data(1);
+    let mut epochs = Vec::new();
+    // Gradient ascent, so every epoch after the first is worse
+    let mut trainer = Trainer::new(nn, Box::new(Mse::new()), Box::new(Sgd::new(-0.1)), &mut StdRng::seed_from_u64(0));
+    trainer.epochs = 10;
+    trainer.batch_size = 8;
+    trainer.add_callback(Recorder(&mut epochs));
+    trainer.add_callback(EarlyStopping::new(2));
+    let history = trainer.fit(&inputs, &targets).unwrap();
+    let restored = trainer.network.weights_to_vec();
+    drop(trainer);
+
+    assert_eq!(history.len(), 3);
+    assert!(history.windows(2).all(|pair| pair[1].loss > pair[0].loss));
+    assert_eq!(restored, epochs[0].0.weights_to_vec());
+    assert_ne!(restored, epochs[2].0.weights_to_vec());
+  }
+
+  #[test]
+  fn validation_split_holds_out_the_last_rows() {
+    let (nn, inputs, targets) = data(2);
+    let fit = |nn: Network, inputs: &Matrix, targets: &Matrix, split: f64, epochs: &mut Vec<(Network, EpochMetrics)>| {
+      let mut trainer = Trainer::new(nn, Box::new(Mse::new()), Box::new(Sgd::new(0.1)), &mut StdRng::seed_from_u64(0));
+      trainer.epochs = 3;
+      trainer.batch_size = 2;
+      trainer.shuffle = false;
+      trainer.validation_split = split;
+      trainer.add_callback(Recorder(epochs));
+      trainer.fit(inputs, targets).unwrap();
+    };
+
+    // 0.25 of 8 rows: trained on the first 6, validated on the last 2
+    let (mut split, mut first_rows) = (Vec::new(), Vec::new());
+    fit(nn.clone(), &inputs, &targets, 0.25, &mut split);
+    fit(nn, &inputs.slice(s![..6, ..]).to_owned(), &targets.slice(s![..6, ..]).to_owned(), 0.0, &mut first_rows);
+
+    let (val_x, val_y) = (inputs.slice(s![6.., ..]).to_owned(), targets.slice(s![6.., ..]).to_owned());
+    for ((nn, metrics), (reference, reference_metrics)) in split.iter().zip(&first_rows) {
+      assert_eq!(nn.weights_to_vec(), reference.weights_to_vec());
+      assert_eq!(metrics.loss, reference_metrics.loss);
+      assert_eq!(metrics.val_loss, Some(Mse::new().value(&nn.output(&val_x).unwrap(), &val_y, None)));
+      assert_eq!(reference_metrics.val_loss, None);
+    }
+  }
+
+  #[test]
+  fn checkpoint_writes_a_loadable_network() {
+    let (nn, inputs, targets) = data(3);
+    let path = std::env::temp_dir().join(format!("rust-nn-{}-checkpoint", std::process::id()));
+    let path = path.to_string_lossy();
+
+    let mut trainer = Trainer::new(nn, Box::new(Mse::new()), Box::new(Sgd::new(0.1)), &mut StdRng::seed_from_u64(0));
+    trainer.epochs = 2;
+    trainer.add_callback(Checkpoint::new(&path, Format::Binary, false));
+    trainer.fit(&inputs, &targets).unwrap();
+
+    let loaded = Network::load(&path, Format::Binary, None, None);
+    std::fs::remove_file(&*path).unwrap();
+    assert_eq!(loaded.unwrap().output(&inputs).unwrap(), trainer.network.output(&inputs).unwrap());
+  }
+}
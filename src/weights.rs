@@ -1,4 +1,4 @@
-use rand::*;
+use rand::{Rng, RngCore};
 use serde::{ Deserialize, Serialize };
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,8 +14,8 @@ impl PartialEq for Weight  {
 }
 
 impl Weight {
-    pub fn random_weight(name: String) -> Weight {
-      Weight { name, value: thread_rng().gen_range(-10000..=10000) as f64 / 40000.0 }
+    pub fn random_weight(name: String, rng: &mut dyn RngCore) -> Weight {
+      Weight { name, value: rng.gen_range(-10000..=10000) as f64 / 40000.0 }
     }
 }
 